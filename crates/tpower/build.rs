@@ -1,3 +1,11 @@
 fn main() {
-    println!("cargo:rustc-link-search=framework=/Library/Apple/System/Library/PrivateFrameworks");
+    println!("cargo::rustc-check-cfg=cfg(apple_ffi)");
+
+    let is_macos = std::env::var("CARGO_CFG_TARGET_OS").is_ok_and(|os| os == "macos");
+    if is_macos && std::env::var_os("CARGO_FEATURE_MACOS").is_some() {
+        println!("cargo:rustc-cfg=apple_ffi");
+        println!(
+            "cargo:rustc-link-search=framework=/Library/Apple/System/Library/PrivateFrameworks"
+        );
+    }
 }
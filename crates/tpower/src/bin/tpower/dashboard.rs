@@ -0,0 +1,254 @@
+use std::{
+    sync::mpsc::{self, Receiver},
+    time::{Duration, SystemTime},
+};
+
+use indexmap::IndexMap;
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Style, Stylize},
+    text::Line,
+    widgets::{Block, Gauge, Paragraph, Sparkline},
+    DefaultTerminal, Frame,
+};
+use tpower::{
+    estimate::{Estimate, EstimateKind, Estimator},
+    provider::{NormalizedResource, PowerDataFrom, PowerStatistic},
+    trace::TraceWriter,
+    util::get_mac_name,
+};
+
+use crate::{
+    sampler::{self, SamplerEvent},
+    DashboardArgs,
+};
+
+#[derive(Default)]
+struct SourceView {
+    stat: PowerStatistic,
+    last: Option<NormalizedResource>,
+    estimator: Estimator,
+    estimate: Option<Estimate>,
+    error: Option<String>,
+    finished: bool,
+}
+
+pub fn run(args: &DashboardArgs) -> anyhow::Result<()> {
+    let (tx, rx) = mpsc::channel();
+    sampler::start(&args.source, args.interval(), &tx)?;
+
+    let mut writer = args.record.as_ref().map(TraceWriter::create).transpose()?;
+
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &rx, |data| {
+        if let Some(writer) = writer.as_mut() {
+            writer.record(data)?;
+        }
+        Ok(())
+    });
+    ratatui::restore();
+
+    if let Some(writer) = writer {
+        writer.finish()?;
+    }
+    result
+}
+
+fn event_loop(
+    terminal: &mut DefaultTerminal,
+    rx: &Receiver<SamplerEvent>,
+    mut on_sample: impl FnMut(&tpower::provider::MergedPowerData) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let mut views: IndexMap<PowerDataFrom, SourceView> = IndexMap::new();
+
+    loop {
+        while let Ok(event) = rx.try_recv() {
+            match event {
+                SamplerEvent::Sample(data) => {
+                    on_sample(&data)?;
+                    let res = NormalizedResource::from(data.as_ref());
+                    let view = views.entry(data.from).or_default();
+                    view.stat
+                        .update(res.battery_power, res.system_in, res.system_load);
+                    view.estimate = view.estimator.update(&res, SystemTime::now());
+                    view.last = Some(res);
+                    view.error = None;
+                    view.finished = false;
+                }
+                SamplerEvent::Error(from, err) => {
+                    views.entry(from).or_default().error = Some(err);
+                }
+                SamplerEvent::Finished(from) => {
+                    views.entry(from).or_default().finished = true;
+                }
+            }
+        }
+
+        terminal.draw(|frame| render(frame, &views))?;
+
+        if event::poll(Duration::from_millis(100))? {
+            if let Event::Key(key) = event::read()? {
+                let quit = matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)
+                    || (key.code == KeyCode::Char('c')
+                        && key.modifiers.contains(KeyModifiers::CONTROL));
+                if key.kind == KeyEventKind::Press && quit {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+fn source_name(from: &PowerDataFrom) -> String {
+    match from {
+        PowerDataFrom::Local => get_mac_name()
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| "Local".to_string()),
+        PowerDataFrom::Remote((udid, name, interface)) => {
+            let name = if name.is_empty() { udid } else { name };
+            format!("{name} ({interface:?})")
+        }
+    }
+}
+
+fn render(frame: &mut Frame, views: &IndexMap<PowerDataFrom, SourceView>) {
+    let [main, help] =
+        Layout::vertical([Constraint::Fill(1), Constraint::Length(1)]).areas(frame.area());
+
+    frame.render_widget(Line::from(" q quit").dark_gray(), help);
+
+    if views.is_empty() {
+        frame.render_widget(
+            Paragraph::new("Waiting for samples...").block(Block::bordered().title(" tpower ")),
+            main,
+        );
+        return;
+    }
+
+    let areas = Layout::vertical(views.iter().map(|_| Constraint::Fill(1))).split(main);
+    for ((from, view), area) in views.iter().zip(areas.iter()) {
+        render_source(frame, *area, &source_name(from), view);
+    }
+}
+
+fn render_source(frame: &mut Frame, area: Rect, name: &str, view: &SourceView) {
+    let status = match (&view.last, view.finished) {
+        (_, true) => "disconnected".to_string(),
+        (Some(res), _) if res.is_charging => "charging".to_string(),
+        (Some(res), _) if res.adapter_name.is_some() => "on adapter".to_string(),
+        (Some(_), _) => "on battery".to_string(),
+        (None, _) => "waiting".to_string(),
+    };
+    let block = Block::bordered()
+        .title(format!(" {name} ").bold())
+        .title(Line::from(format!(" {status} ")).right_aligned());
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let Some(res) = &view.last else {
+        if let Some(err) = &view.error {
+            frame.render_widget(Paragraph::new(err.as_str()).red(), inner);
+        }
+        return;
+    };
+
+    let [header, info, sparklines] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Length(1),
+        Constraint::Fill(1),
+    ])
+    .areas(inner);
+
+    frame.render_widget(
+        Gauge::default()
+            .gauge_style(Style::default().fg(level_color(res.battery_level)))
+            .percent(res.battery_level.clamp(0, 100) as u16)
+            .label(format!("{}%", res.battery_level)),
+        header,
+    );
+
+    let adapter = res.adapter_name.as_ref().map_or_else(String::new, |name| {
+        format!(
+            "  {name} {:.0} W ({:.1} V / {:.2} A)",
+            res.adapter_watts, res.adapter_voltage, res.adapter_amperage
+        )
+    });
+    let thermal = res.thermal.as_ref().map_or_else(String::new, |thermal| {
+        let cpu = thermal
+            .cpu
+            .map_or_else(String::new, |cpu| format!("  CPU {cpu:.0} °C"));
+        let fans = thermal
+            .fans
+            .iter()
+            .map(|fan| format!(" {:.0}", fan.actual))
+            .collect::<String>();
+        if fans.is_empty() {
+            cpu
+        } else {
+            format!("{cpu}  fans{fans} rpm")
+        }
+    });
+    let estimate = view.estimate.map_or_else(String::new, |estimate| {
+        let remaining = Duration::from_secs(estimate.remaining.as_secs() / 60 * 60);
+        let kind = match estimate.kind {
+            EstimateKind::ToFull => "to full",
+            EstimateKind::ToEmpty => "to empty",
+        };
+        format!(
+            "  {} {kind} ({:.0}%)",
+            humantime::format_duration(remaining),
+            estimate.confidence * 100.
+        )
+    });
+    let mut line = format!(
+        "{:.1} °C  {} cycles  {}/{} mAh{estimate}{adapter}{thermal}",
+        res.temperature, res.cycle_count, res.current_capacity, res.max_capacity,
+    );
+    if let Some(err) = &view.error {
+        line.push_str(&format!("  ! {err}"));
+    }
+    frame.render_widget(Paragraph::new(line), info);
+
+    let [system, battery, input] = Layout::horizontal([Constraint::Fill(1); 3]).areas(sparklines);
+    let width = |area: Rect| area.width.saturating_sub(2) as usize;
+
+    frame.render_widget(
+        Sparkline::default()
+            .block(Block::bordered().title(format!(
+                " System {:.2} W (max {:.2}) ",
+                res.system_load, view.stat.max_system_power
+            )))
+            .data(view.stat.system_history(width(system)))
+            .style(Color::Cyan),
+        system,
+    );
+    frame.render_widget(
+        Sparkline::default()
+            .block(Block::bordered().title(format!(
+                " Battery {:.2} W (max {:.2}) ",
+                res.battery_power, view.stat.max_battery_power
+            )))
+            .data(view.stat.battery_history(width(battery)))
+            .style(Color::Green),
+        battery,
+    );
+    frame.render_widget(
+        Sparkline::default()
+            .block(Block::bordered().title(format!(
+                " Adapter {:.2} W (max {:.2}) ",
+                res.system_in, view.stat.max_input_power
+            )))
+            .data(view.stat.input_history(width(input)))
+            .style(Color::Yellow),
+        input,
+    );
+}
+
+fn level_color(level: i32) -> Color {
+    match level {
+        ..=10 => Color::Red,
+        11..=30 => Color::Yellow,
+        _ => Color::Green,
+    }
+}
@@ -0,0 +1,128 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::c_void,
+    mem::MaybeUninit,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Sender},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use tpower::{
+    ffi::{
+        core_foundation::runloop::CFRunLoopRun, wrapper::Device, AMDeviceNotificationCallbackInfo,
+        AMDeviceNotificationSubscribe, Action,
+    },
+    provider::{remote::RemoteSource, MergedPowerData, PowerDataFrom, PowerSource},
+};
+
+use crate::sampler::{self, SamplerEvent};
+
+fn listen() -> mpsc::Receiver<(Device, Action)> {
+    let (tx, rx) = mpsc::channel::<(Device, Action)>();
+
+    extern "C" fn callback(info: *const AMDeviceNotificationCallbackInfo, context: *mut c_void) {
+        let tx = unsafe { &*(context as *const Sender<(Device, Action)>) };
+        let info = unsafe { *info };
+        let device = unsafe { Device::new(info.device) };
+
+        let _ = tx.send((device, info.action));
+    }
+
+    thread::spawn(move || {
+        // lives as long as the run loop, i.e. the whole process
+        let tx = Box::leak(Box::new(tx));
+        let mut not = MaybeUninit::uninit();
+        unsafe {
+            AMDeviceNotificationSubscribe(
+                callback,
+                0,
+                0,
+                tx as *mut Sender<_> as *mut _,
+                not.as_mut_ptr(),
+            )
+        };
+        unsafe { CFRunLoopRun() };
+    });
+
+    rx
+}
+
+/// Start a sampler for every iOS device that is attached, and stop it once
+/// the device is detached.
+pub fn watch(interval: Duration, tx: Sender<SamplerEvent>) {
+    let rx = listen();
+
+    thread::spawn(move || {
+        let mut devices: HashMap<String, (Device, Arc<AtomicBool>)> = HashMap::new();
+
+        for (device, action) in rx {
+            match action {
+                // a device attached over both USB and WiFi is only sampled once
+                Action::Attached if devices.contains_key(&device.udid) => (),
+                Action::Attached => {
+                    // must create the source after `device.prepare_device()`
+                    // or name will be empty
+                    let source = device
+                        .prepare_device()
+                        .and_then(|_| RemoteSource::new(&device));
+                    let source = match source {
+                        Ok(source) => source,
+                        Err(err) => {
+                            let from = PowerDataFrom::Remote((
+                                device.udid.clone(),
+                                String::new(),
+                                device.interface_type,
+                            ));
+                            let _ = tx.send(SamplerEvent::Error(from, err.to_string()));
+                            continue;
+                        }
+                    };
+                    let stop = sampler::spawn(Box::new(source), Some(interval), tx.clone());
+                    devices.insert(device.udid.clone(), (device, stop));
+                }
+                Action::Detached => {
+                    // the sampler reports `Finished` once it notices
+                    if let Some((_, stop)) = devices.remove(&device.udid) {
+                        stop.store(true, Ordering::Relaxed);
+                    }
+                }
+                _ => (),
+            }
+        }
+    });
+}
+
+/// Sample every device that attaches within `wait` once.
+///
+/// Devices that are already connected are reported right after subscribing,
+/// so a short wait is usually enough.
+pub fn sample_attached(wait: Duration) -> Vec<MergedPowerData> {
+    let rx = listen();
+    let deadline = Instant::now() + wait;
+
+    let mut seen = HashSet::new();
+    let mut samples = vec![];
+    while let Some(timeout) = deadline.checked_duration_since(Instant::now()) {
+        let Ok((device, action)) = rx.recv_timeout(timeout) else {
+            break;
+        };
+        if action != Action::Attached || !seen.insert(device.udid.clone()) {
+            continue;
+        }
+
+        let sample = device
+            .prepare_device()
+            .and_then(|_| RemoteSource::new(&device))
+            .map_err(Into::into)
+            .and_then(|mut source| source.sample());
+        match sample {
+            Ok(data) => samples.push(data),
+            Err(err) => eprintln!("skipping {}: {err}", device.udid),
+        }
+    }
+    samples
+}
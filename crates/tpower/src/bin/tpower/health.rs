@@ -0,0 +1,114 @@
+use std::{
+    path::PathBuf,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use anyhow::bail;
+use clap::Args;
+use tpower::{
+    health::{CapacitySample, HealthReport, DEFAULT_THRESHOLDS},
+    provider::{NormalizedResource, PowerDataFrom},
+    trace::TraceReader,
+};
+
+use crate::{sampler::local_source, LocalArgs};
+
+#[derive(Debug, Args)]
+pub struct HealthArgs {
+    /// Fit the fade over the local samples of these traces as well
+    #[arg(value_name = "TRACE")]
+    traces: Vec<PathBuf>,
+
+    /// State of health to project, in percent, can be repeated [default: 80]
+    #[arg(short, long = "threshold", value_name = "PERCENT")]
+    thresholds: Vec<f32>,
+
+    /// Print the report as JSON
+    #[arg(long)]
+    json: bool,
+
+    #[command(flatten)]
+    local: LocalArgs,
+}
+
+pub fn run(args: &HealthArgs) -> anyhow::Result<()> {
+    let mut history = vec![];
+    for path in &args.traces {
+        for frame in TraceReader::open(path)? {
+            let frame = frame?;
+            if frame.data.from == PowerDataFrom::Local {
+                history.push(CapacitySample::from(&NormalizedResource::from(&frame.data)));
+            }
+        }
+    }
+
+    match local_source(&args.local).and_then(|mut source| source.sample()) {
+        Ok(data) => history.push(CapacitySample::from(&NormalizedResource::from(&data))),
+        Err(err) if !history.is_empty() => log::warn!("Failed to sample: {err}"),
+        Err(err) => return Err(err),
+    }
+
+    let thresholds = match args.thresholds.as_slice() {
+        [] => &DEFAULT_THRESHOLDS[..],
+        thresholds => thresholds,
+    };
+    let Some(report) = HealthReport::new(history, thresholds) else {
+        bail!("the battery doesn't report its capacity");
+    };
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    let current = &report.current;
+    println!(
+        "State of health  {:.1}% ({}/{} mAh, {} cycles)",
+        report.state_of_health, current.max_capacity, current.design_capacity, current.cycle_count
+    );
+    let fades = [
+        report
+            .fade_per_cycle
+            .map(|fade| format!("{fade:.3}% per cycle")),
+        report
+            .fade_per_day
+            .map(|fade| format!("{fade:.3}% per day")),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>();
+    if fades.is_empty() {
+        println!("Fade             not enough history yet");
+    } else {
+        println!(
+            "Fade             {} over {} samples",
+            fades.join(", "),
+            report.samples
+        );
+    }
+
+    for projection in &report.projections {
+        let when = if projection.reached {
+            "reached".to_string()
+        } else {
+            let cycles = projection
+                .cycle_count
+                .map(|cycles| format!("at {cycles} cycles"));
+            let date = projection.timestamp.map(|timestamp| {
+                let at = UNIX_EPOCH + Duration::from_secs(timestamp.max(0) as u64);
+                let date = humantime::format_rfc3339_seconds(at).to_string();
+                format!("around {}", &date[..10])
+            });
+            match (cycles, date) {
+                (None, None) => "unknown".to_string(),
+                (cycles, date) => [cycles, date]
+                    .into_iter()
+                    .flatten()
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            }
+        };
+        println!("{:<17}{when}", format!("{}%", projection.threshold));
+    }
+    Ok(())
+}
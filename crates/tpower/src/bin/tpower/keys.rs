@@ -0,0 +1,37 @@
+use anyhow::anyhow;
+use clap::Args;
+use tpower::ffi::smc::SMCConnection;
+
+#[derive(Debug, Args)]
+pub struct KeysArgs {
+    /// Only list keys starting with this, e.g. `P` for power keys
+    prefix: Option<String>,
+
+    /// Print JSON instead of a table
+    #[arg(long)]
+    json: bool,
+}
+
+pub fn run(args: &KeysArgs) -> anyhow::Result<()> {
+    let mut smc =
+        SMCConnection::new("AppleSMC").map_err(|err| anyhow!("could not open AppleSMC: {err}"))?;
+    let entries = smc
+        .keys_matching(args.prefix.as_deref().unwrap_or_default())
+        .map_err(|err| anyhow!("could not read the key count: {err}"))?;
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+
+    for entry in entries {
+        let value = entry
+            .value
+            .map_or_else(|| "-".to_string(), |v| v.to_string());
+        println!(
+            "{:<4}  {:<4}  {:>2}  {:#04x}  {value}",
+            entry.key, entry.data_type, entry.data_size, entry.data_attributes
+        );
+    }
+    Ok(())
+}
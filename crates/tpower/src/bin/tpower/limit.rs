@@ -0,0 +1,90 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use clap::Args;
+use signal_hook::consts::TERM_SIGNALS;
+use tpower::{
+    charge::{ChargeLimit, ChargeLimiter},
+    ffi::smc::ChargeInhibitor,
+};
+
+use crate::{sampler::local_source, LocalArgs};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Args)]
+pub struct LimitArgs {
+    /// Stop charging at this battery level
+    #[arg(default_value_t = 80)]
+    limit: i32,
+
+    /// Charge again once the battery drops to this level [default: 5 below
+    /// the limit]
+    #[arg(short, long)]
+    resume: Option<i32>,
+
+    /// Sampling interval in seconds
+    #[arg(short, long, default_value_t = 30, value_parser = clap::value_parser!(u64).range(1..))]
+    interval: u64,
+
+    #[command(flatten)]
+    local: LocalArgs,
+}
+
+/// Hold the battery between the resume level and the limit until
+/// interrupted, charging is allowed again on the way out.
+pub fn run(args: &LimitArgs) -> anyhow::Result<()> {
+    let limit = ChargeLimit::new(args.limit, args.resume.unwrap_or(args.limit - 5))?;
+    let mut source = local_source(&args.local)?;
+    let mut inhibitor =
+        ChargeInhibitor::open().map_err(|err| anyhow!("could not open AppleSMC: {err}"))?;
+    // an earlier run may have been killed while charging was inhibited
+    inhibitor
+        .restore()
+        .map_err(|err| anyhow!("could not write to the SMC: {err}"))?;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    for signal in TERM_SIGNALS {
+        signal_hook::flag::register(*signal, stop.clone())?;
+    }
+
+    let mut limiter = ChargeLimiter::new(limit);
+    let interval = Duration::from_secs(args.interval);
+    println!(
+        "Limiting charge to {}%, resuming at {}%",
+        limit.limit(),
+        limit.resume()
+    );
+
+    while !stop.load(Ordering::Relaxed) {
+        let started = Instant::now();
+        match source.sample_normalized() {
+            Ok(res) => {
+                if let Some(state) = limiter.update_with(&res) {
+                    inhibitor
+                        .apply(state)
+                        .map_err(|err| anyhow!("could not write to the SMC: {err}"))?;
+                    println!("{}% -> {state:?}", res.battery_level);
+                }
+            }
+            Err(err) => log::warn!("Failed to sample: {err}"),
+        }
+
+        while !stop.load(Ordering::Relaxed) && started.elapsed() < interval {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    inhibitor
+        .restore()
+        .map_err(|err| anyhow!("could not allow charging again: {err}"))?;
+    println!("Charging is left to the system again");
+    Ok(())
+}
@@ -0,0 +1,113 @@
+use std::{path::PathBuf, time::Duration};
+
+use clap::{Args, Parser, Subcommand};
+
+mod dashboard;
+#[cfg(apple_ffi)]
+mod devices;
+mod health;
+#[cfg(apple_ffi)]
+mod keys;
+#[cfg(apple_ffi)]
+mod limit;
+mod sampler;
+mod serve;
+mod snapshot;
+
+/// Monitor power usage of the Mac and connected iOS devices
+#[derive(Debug, Parser)]
+#[command(version)]
+#[command(args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    dashboard: DashboardArgs,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Show a live dashboard, the default
+    Dashboard(DashboardArgs),
+    /// Take a single sample and print it
+    Snapshot(snapshot::SnapshotArgs),
+    /// Serve live metrics for Prometheus to scrape
+    Serve(serve::ServeArgs),
+    /// Show the battery's state of health and project how it fades
+    Health(health::HealthArgs),
+    /// List SMC keys with their type and current value
+    #[cfg(apple_ffi)]
+    Keys(keys::KeysArgs),
+    /// Hold the battery below a charge limit while plugged in
+    #[cfg(apple_ffi)]
+    Limit(limit::LimitArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct LocalArgs {
+    /// Load SMC sensor profiles from this TOML or JSON file
+    #[cfg(apple_ffi)]
+    #[arg(long, value_name = "FILE")]
+    smc_profiles: Option<PathBuf>,
+
+    /// Read from this directory instead of /sys/class/power_supply
+    #[cfg(not(apple_ffi))]
+    #[arg(long, default_value = tpower::provider::sysfs::DEFAULT_SYSFS_ROOT)]
+    sysfs_root: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct SourceArgs {
+    /// Don't sample connected iOS devices
+    #[cfg(apple_ffi)]
+    #[arg(long)]
+    no_devices: bool,
+
+    #[command(flatten)]
+    local: LocalArgs,
+
+    /// Play back a trace instead of sampling the hardware
+    #[arg(long, value_name = "TRACE")]
+    replay: Option<PathBuf>,
+
+    /// Replay speed factor, 0 plays the trace as fast as possible
+    #[arg(long, default_value_t = 1.0, requires = "replay")]
+    speed: f32,
+}
+
+#[derive(Debug, Args)]
+pub struct DashboardArgs {
+    /// Sampling interval in milliseconds
+    #[arg(short, long, default_value_t = 1000, value_parser = clap::value_parser!(u64).range(100..))]
+    interval: u64,
+
+    /// Record every sample to a trace file
+    #[arg(long, value_name = "TRACE", conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    #[command(flatten)]
+    source: SourceArgs,
+}
+
+impl DashboardArgs {
+    fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval)
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    match cli.command {
+        Some(Command::Dashboard(args)) => dashboard::run(&args),
+        Some(Command::Snapshot(args)) => snapshot::run(&args),
+        Some(Command::Serve(args)) => serve::run(&args),
+        Some(Command::Health(args)) => health::run(&args),
+        #[cfg(apple_ffi)]
+        Some(Command::Keys(args)) => keys::run(&args),
+        #[cfg(apple_ffi)]
+        Some(Command::Limit(args)) => limit::run(&args),
+        None => dashboard::run(&cli.dashboard),
+    }
+}
@@ -0,0 +1,117 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use indexmap::IndexSet;
+use tpower::{
+    provider::{MergedPowerData, PowerDataFrom, PowerSource},
+    trace::{ReplaySource, ReplaySpeed},
+};
+
+use crate::{LocalArgs, SourceArgs};
+
+pub enum SamplerEvent {
+    Sample(Box<MergedPowerData>),
+    Error(PowerDataFrom, String),
+    /// The source is gone, e.g. a device was detached or a replay ended.
+    Finished(PowerDataFrom),
+}
+
+/// Sample `source` on its own thread until `stop` is set.
+///
+/// Without an `interval` the source is expected to pace itself, like a
+/// [`ReplaySource`] does, and the sampler stops at the first error.
+pub fn spawn(
+    mut source: Box<dyn PowerSource + Send>,
+    interval: Option<Duration>,
+    tx: Sender<SamplerEvent>,
+) -> Arc<AtomicBool> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let stopped = stop.clone();
+
+    thread::spawn(move || {
+        let from = source.identity();
+        while !stopped.load(Ordering::Relaxed) {
+            let event = match source.sample() {
+                Ok(data) => SamplerEvent::Sample(Box::new(data)),
+                Err(_) if interval.is_none() => break,
+                Err(err) => SamplerEvent::Error(from.clone(), err.to_string()),
+            };
+            if tx.send(event).is_err() {
+                return;
+            }
+            if let Some(interval) = interval {
+                thread::sleep(interval);
+            }
+        }
+        let _ = tx.send(SamplerEvent::Finished(from));
+    });
+
+    stop
+}
+
+/// Start sampling everything `args` asks for.
+pub fn start(
+    args: &SourceArgs,
+    interval: Duration,
+    tx: &Sender<SamplerEvent>,
+) -> anyhow::Result<()> {
+    if let Some(path) = &args.replay {
+        let speed = if args.speed > 0. {
+            ReplaySpeed::Accelerated(args.speed)
+        } else {
+            ReplaySpeed::Unthrottled
+        };
+        let replay = ReplaySource::open(path, speed)?;
+
+        // one replay per recorded source, they share the trace's clock
+        let froms = replay
+            .frames()
+            .iter()
+            .map(|f| f.data.from.clone())
+            .collect::<IndexSet<_>>();
+        for from in froms {
+            let source = ReplaySource::new(replay.frames().to_vec(), speed).only(&from);
+            spawn(Box::new(source), None, tx.clone());
+        }
+        return Ok(());
+    }
+
+    spawn(local_source(&args.local)?, Some(interval), tx.clone());
+
+    #[cfg(apple_ffi)]
+    if !args.no_devices {
+        crate::devices::watch(interval, tx.clone());
+    }
+
+    Ok(())
+}
+
+#[cfg(apple_ffi)]
+pub fn local_source(args: &LocalArgs) -> anyhow::Result<Box<dyn PowerSource + Send>> {
+    use tpower::{ffi::smc::SensorProfiles, provider::local::LocalSource};
+
+    let profiles = match &args.smc_profiles {
+        Some(path) => SensorProfiles::load(path)?,
+        None => SensorProfiles::bundled(),
+    };
+    Ok(Box::new(LocalSource::with_profiles(&profiles)))
+}
+
+#[cfg(not(apple_ffi))]
+pub fn local_source(args: &LocalArgs) -> anyhow::Result<Box<dyn PowerSource + Send>> {
+    let source = tpower::provider::sysfs::SysfsSource::with_root(&args.sysfs_root);
+    if !source.root().exists() {
+        return Err(anyhow::anyhow!(
+            "{} does not exist",
+            source.root().display()
+        ));
+    }
+    Ok(Box::new(source))
+}
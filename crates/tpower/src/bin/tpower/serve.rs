@@ -0,0 +1,53 @@
+use std::{
+    net::SocketAddr,
+    sync::{mpsc, Arc},
+    time::Duration,
+};
+
+use clap::Args;
+use tpower::{
+    export::{Metrics, MetricsServer},
+    provider::NormalizedResource,
+};
+
+use crate::{
+    sampler::{self, SamplerEvent},
+    SourceArgs,
+};
+
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    /// Address to serve metrics on
+    #[arg(short, long, default_value = "127.0.0.1:9101")]
+    listen: SocketAddr,
+
+    /// Sampling interval in milliseconds
+    #[arg(short, long, default_value_t = 1000, value_parser = clap::value_parser!(u64).range(100..))]
+    interval: u64,
+
+    #[command(flatten)]
+    source: SourceArgs,
+}
+
+pub fn run(args: &ServeArgs) -> anyhow::Result<()> {
+    let metrics = Arc::new(Metrics::default());
+    let server = MetricsServer::bind(args.listen, metrics.clone())?;
+    eprintln!("serving metrics at http://{}/metrics", server.local_addr());
+
+    let (tx, rx) = mpsc::channel();
+    sampler::start(&args.source, Duration::from_millis(args.interval), &tx)?;
+    // the loop ends once every sampler is done, e.g. a replay is over
+    drop(tx);
+
+    for event in rx {
+        match event {
+            SamplerEvent::Sample(data) => {
+                metrics.update(&data.from, &NormalizedResource::from(data.as_ref()))
+            }
+            SamplerEvent::Error(from, err) => eprintln!("{from:?}: {err}"),
+            SamplerEvent::Finished(from) => metrics.remove(&from),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,63 @@
+#[cfg(apple_ffi)]
+use std::time::Duration;
+use std::{
+    io::{self, Write},
+    iter,
+};
+
+use clap::{Args, ValueEnum};
+use tpower::{export, provider::NormalizedResource};
+
+use crate::{sampler::local_source, LocalArgs};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Format {
+    Json,
+    Csv,
+    /// Prometheus text exposition format
+    Prom,
+}
+
+#[derive(Debug, Args)]
+pub struct SnapshotArgs {
+    #[arg(short, long, value_enum, default_value_t = Format::Json)]
+    format: Format,
+
+    /// Also sample connected iOS devices
+    #[cfg(apple_ffi)]
+    #[arg(long)]
+    devices: bool,
+
+    /// How long to wait for devices to show up, in milliseconds
+    #[cfg(apple_ffi)]
+    #[arg(long, default_value_t = 1000, requires = "devices")]
+    wait: u64,
+
+    #[command(flatten)]
+    local: LocalArgs,
+}
+
+pub fn run(args: &SnapshotArgs) -> anyhow::Result<()> {
+    let local = local_source(&args.local)?.sample()?;
+
+    #[cfg(apple_ffi)]
+    let remote = match args.devices {
+        true => crate::devices::sample_attached(Duration::from_millis(args.wait)),
+        false => vec![],
+    };
+    #[cfg(not(apple_ffi))]
+    let remote = vec![];
+
+    let samples = iter::once(local)
+        .chain(remote)
+        .map(|data| (data.from.clone(), NormalizedResource::from(&data)))
+        .collect::<Vec<_>>();
+
+    let output = match args.format {
+        Format::Json => serde_json::to_string_pretty(&export::to_json(&samples))? + "\n",
+        Format::Csv => export::to_csv(&samples),
+        Format::Prom => export::to_prometheus(&samples),
+    };
+    io::stdout().write_all(output.as_bytes())?;
+    Ok(())
+}
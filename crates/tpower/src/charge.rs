@@ -0,0 +1,161 @@
+//! Keep the battery between two levels while the Mac stays plugged in.
+//!
+//! [`ChargeLimiter`] only decides, the SMC side lives in
+//! [`ChargeInhibitor`](crate::ffi::smc::ChargeInhibitor).
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::provider::NormalizedResource;
+
+#[derive(Debug, Error)]
+pub enum ChargeLimitError {
+    #[error("the limit has to be within 1..=100, got {0}")]
+    InvalidLimit(i32),
+
+    #[error("the resume level has to be below the limit ({limit}), got {resume}")]
+    InvalidResume { limit: i32, resume: i32 },
+}
+
+/// Stop charging at `limit` percent, start again once the battery drained
+/// to `resume`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[serde(rename_all = "camelCase")]
+pub struct ChargeLimit {
+    limit: i32,
+    resume: i32,
+}
+
+impl ChargeLimit {
+    pub fn new(limit: i32, resume: i32) -> Result<Self, ChargeLimitError> {
+        if !(1..=100).contains(&limit) {
+            return Err(ChargeLimitError::InvalidLimit(limit));
+        }
+        if !(0..limit).contains(&resume) {
+            return Err(ChargeLimitError::InvalidResume { limit, resume });
+        }
+        Ok(Self { limit, resume })
+    }
+
+    pub fn limit(&self) -> i32 {
+        self.limit
+    }
+
+    pub fn resume(&self) -> i32 {
+        self.resume
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[serde(rename_all = "camelCase")]
+pub enum ChargeState {
+    /// Charging is left to the system
+    Allowed,
+    /// Charging is held off until the battery drops to the resume level
+    Inhibited,
+}
+
+/// The hysteresis between [`ChargeLimit::limit`] and
+/// [`ChargeLimit::resume`], fed one sample at a time.
+#[derive(Debug, Clone)]
+pub struct ChargeLimiter {
+    limit: ChargeLimit,
+    state: ChargeState,
+}
+
+impl ChargeLimiter {
+    pub fn new(limit: ChargeLimit) -> Self {
+        Self {
+            limit,
+            state: ChargeState::Allowed,
+        }
+    }
+
+    pub fn limit(&self) -> ChargeLimit {
+        self.limit
+    }
+
+    pub fn state(&self) -> ChargeState {
+        self.state
+    }
+
+    /// Feed a sample, returns the state the hardware has to be put in if it
+    /// needs to be driven.
+    ///
+    /// Besides transitions, that is when the battery charges although it
+    /// should be inhibited, e.g. the SMC dropped the inhibit over sleep.
+    pub fn update(&mut self, battery_level: i32, is_charging: bool) -> Option<ChargeState> {
+        let next = match self.state {
+            ChargeState::Allowed if battery_level >= self.limit.limit => ChargeState::Inhibited,
+            ChargeState::Inhibited if battery_level <= self.limit.resume => ChargeState::Allowed,
+            state => state,
+        };
+
+        if next != self.state {
+            self.state = next;
+            return Some(next);
+        }
+        (next == ChargeState::Inhibited && is_charging).then_some(next)
+    }
+
+    pub fn update_with(&mut self, res: &NormalizedResource) -> Option<ChargeState> {
+        self.update(res.battery_level, res.is_charging)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter() -> ChargeLimiter {
+        ChargeLimiter::new(ChargeLimit::new(80, 75).unwrap())
+    }
+
+    #[test]
+    fn validates_limits() {
+        assert!(matches!(
+            ChargeLimit::new(0, 0),
+            Err(ChargeLimitError::InvalidLimit(0))
+        ));
+        assert!(matches!(
+            ChargeLimit::new(101, 90),
+            Err(ChargeLimitError::InvalidLimit(101))
+        ));
+        assert!(matches!(
+            ChargeLimit::new(80, 80),
+            Err(ChargeLimitError::InvalidResume { .. })
+        ));
+        assert!(ChargeLimit::new(100, 0).is_ok());
+    }
+
+    #[test]
+    fn inhibits_at_the_limit_and_resumes_below() {
+        let mut limiter = limiter();
+        assert_eq!(limiter.update(50, true), None);
+        assert_eq!(limiter.update(79, true), None);
+        assert_eq!(limiter.update(80, true), Some(ChargeState::Inhibited));
+        assert_eq!(limiter.state(), ChargeState::Inhibited);
+
+        // held between the two levels
+        assert_eq!(limiter.update(79, false), None);
+        assert_eq!(limiter.update(76, false), None);
+        assert_eq!(limiter.update(75, false), Some(ChargeState::Allowed));
+        assert_eq!(limiter.state(), ChargeState::Allowed);
+
+        // charging back up through the band doesn't inhibit early
+        assert_eq!(limiter.update(78, true), None);
+        assert_eq!(limiter.update(81, true), Some(ChargeState::Inhibited));
+    }
+
+    #[test]
+    fn drives_the_inhibit_again_while_charging() {
+        let mut limiter = limiter();
+        assert_eq!(limiter.update(85, false), Some(ChargeState::Inhibited));
+        assert_eq!(limiter.update(85, false), None);
+        // e.g. the SMC dropped the inhibit over sleep
+        assert_eq!(limiter.update(85, true), Some(ChargeState::Inhibited));
+        assert_eq!(limiter.state(), ChargeState::Inhibited);
+    }
+}
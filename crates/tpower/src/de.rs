@@ -1,18 +1,57 @@
-use std::ops::Deref;
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::File,
+    io::{self, BufReader, Cursor, Read, Seek},
+    ops::Deref,
+    path::Path,
+};
 
-use serde::{Deserialize, Serialize};
+use serde::{
+    de::{self, DeserializeOwned, Deserializer, Visitor},
+    Deserialize, Serialize,
+};
+use thiserror::Error;
 
+/// Declare each struct twice, as is and in `repr` with the serde attributes
+/// of the IORegistry, and convert the latter field by field.
 macro_rules! with_repr {
     ($(
         #[out, $($out:meta),*]
         #[repr, $repr:meta]
         #[$($meta:meta),*]
-        $item:item
+        $(#[doc = $doc:expr])*
+        pub struct $name:ident {
+            $(
+                $(#[$field_meta:meta])*
+                pub $field:ident: $ty:ty
+            ),* $(,)?
+        }
     )*) => {
         $(
             $(#[$meta])*
             $(#[$out])*
-            $item
+            $(#[doc = $doc])*
+            pub struct $name {
+                $(
+                    $(#[$field_meta])*
+                    pub $field: $ty
+                ),*
+            }
+
+            impl FromRepr<repr::$name> for $name {
+                fn from_repr(value: repr::$name) -> Self {
+                    Self {
+                        $($field: FromRepr::from_repr(value.$field)),*
+                    }
+                }
+            }
+
+            impl From<repr::$name> for $name {
+                fn from(value: repr::$name) -> Self {
+                    Self::from_repr(value)
+                }
+            }
         )*
 
         pub mod repr {
@@ -20,44 +59,86 @@ macro_rules! with_repr {
             $(
                 $(#[$meta])*
                 #[$repr]
-                $item
+                $(#[doc = $doc])*
+                pub struct $name {
+                    $(
+                        $(#[$field_meta])*
+                        pub $field: $ty
+                    ),*
+                }
             )*
         }
     };
 }
 
+/// Conversion from the `repr` form of a field, the identity for anything
+/// but the structs declared through [`with_repr`].
+trait FromRepr<T> {
+    fn from_repr(value: T) -> Self;
+}
+
+impl<T, U: FromRepr<T>> FromRepr<Option<T>> for Option<U> {
+    fn from_repr(value: Option<T>) -> Self {
+        value.map(U::from_repr)
+    }
+}
+
+macro_rules! same_repr {
+    ($($ty:ty),*) => {
+        $(
+            impl FromRepr<$ty> for $ty {
+                fn from_repr(value: $ty) -> Self {
+                    value
+                }
+            }
+        )*
+    };
+}
+
+same_repr!(bool, i32, i64, String, Vec<i32>, BTreeMap<String, plist::Value>);
+
 with_repr! {
-    #[out, serde(rename_all = "camelCase"), cfg_attr(feature = "specta", derive(specta::Type))]
+    #[out, serde(rename_all = "camelCase")]
     #[repr, serde(rename_all(deserialize = "PascalCase", serialize = "camelCase"))]
-    #[derive(Debug, Clone, Deserialize, Serialize)]
+    #[derive(Debug, Clone, Deserialize, Serialize), cfg_attr(feature = "specta", derive(specta::Type))]
     pub struct IORegistryDiagnostic {
         pub diagnostics: Diagnostics,
     }
 
-    #[out, serde(rename_all = "camelCase"), cfg_attr(feature = "specta", derive(specta::Type))]
+    #[out, serde(rename_all = "camelCase")]
     #[repr, serde(rename_all(deserialize = "PascalCase", serialize = "camelCase"))]
-    #[derive(Debug, Clone, Deserialize, Serialize)]
+    #[derive(Debug, Clone, Deserialize, Serialize), cfg_attr(feature = "specta", derive(specta::Type))]
     pub struct Diagnostics {
         #[serde(rename = "IORegistry")]
         pub ioregistry: IORegistry,
     }
 
-    #[out, serde(rename_all = "camelCase"), cfg_attr(feature = "specta", derive(specta::Type))]
+    #[out, serde(rename_all = "camelCase")]
     #[repr, serde(rename_all(deserialize = "PascalCase", serialize = "camelCase"))]
-    #[derive(Debug, Clone, Default, Deserialize, Serialize)]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize), cfg_attr(feature = "specta", derive(specta::Type))]
     pub struct AdapterDetails {
+        #[serde(default, deserialize_with = "lenient")]
         pub adapter_voltage: Option<i32>,
+        #[serde(default, deserialize_with = "lenient")]
         pub is_wireless: Option<bool>,
+        #[serde(default, deserialize_with = "lenient")]
         pub watts: Option<i32>,
+        #[serde(default, deserialize_with = "lenient")]
         pub name: Option<String>,
+        #[serde(default, deserialize_with = "lenient")]
         pub current: Option<i32>,
+        #[serde(default, deserialize_with = "lenient")]
         pub description: Option<String>,
+        /// Keys we don't model, as reported
+        #[serde(default)]
+        #[cfg_attr(feature = "specta", specta(skip))]
+        pub extra: BTreeMap<String, plist::Value>,
     }
 
 
-    #[out, serde(rename_all = "camelCase"), cfg_attr(feature = "specta", derive(specta::Type))]
+    #[out, serde(rename_all = "camelCase")]
     #[repr, serde(rename_all(deserialize = "PascalCase", serialize = "camelCase"))]
-    #[derive(Debug, Clone, Default, Deserialize, Serialize)]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize), cfg_attr(feature = "specta", derive(specta::Type))]
     pub struct PowerTelemetryData {
         pub adapter_efficiency_loss: i32,
         pub battery_power: i64,
@@ -68,15 +149,81 @@ with_repr! {
         pub system_voltage_in: i32,
     }
 
-    #[out, serde(rename_all = "camelCase"), cfg_attr(feature = "specta", derive(specta::Type))]
+    #[out, serde(rename_all = "camelCase")]
     #[repr, serde(rename_all(deserialize = "PascalCase", serialize = "camelCase"))]
-    #[derive(Debug, Clone, Default, Deserialize, Serialize)]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize), cfg_attr(feature = "specta", derive(specta::Type))]
+    pub struct BatteryData {
+        /// mV, one per cell
+        #[serde(default, deserialize_with = "lenient")]
+        pub cell_voltage: Option<Vec<i32>>,
+        /// mAh, one per cell
+        #[serde(default, deserialize_with = "lenient")]
+        pub qmax: Option<Vec<i32>>,
+        #[serde(default, deserialize_with = "lenient")]
+        pub state_of_charge: Option<i32>,
+        #[serde(alias = "DesignCycleCount9C", default, deserialize_with = "lenient")]
+        pub design_cycle_count: Option<i32>,
+        #[serde(default, deserialize_with = "lenient")]
+        pub lifetime_data: Option<LifetimeData>,
+    }
+
+    #[out, serde(rename_all = "camelCase")]
+    #[repr, serde(rename_all(deserialize = "PascalCase", serialize = "camelCase"))]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize), cfg_attr(feature = "specta", derive(specta::Type))]
+    /// Extremes the gas gauge recorded over the life of the battery.
+    pub struct LifetimeData {
+        #[serde(default, deserialize_with = "lenient")]
+        pub maximum_temperature: Option<i32>,
+        #[serde(default, deserialize_with = "lenient")]
+        pub minimum_temperature: Option<i32>,
+        #[serde(default, deserialize_with = "lenient")]
+        pub average_temperature: Option<i32>,
+        /// mV
+        #[serde(default, deserialize_with = "lenient")]
+        pub maximum_pack_voltage: Option<i32>,
+        #[serde(default, deserialize_with = "lenient")]
+        pub minimum_pack_voltage: Option<i32>,
+        /// mA
+        #[serde(default, deserialize_with = "lenient")]
+        pub maximum_charge_current: Option<i32>,
+        #[serde(default, deserialize_with = "lenient")]
+        pub maximum_discharge_current: Option<i32>,
+        /// hours
+        #[serde(default, deserialize_with = "lenient")]
+        pub total_operating_time: Option<i64>,
+    }
+
+    #[out, serde(rename_all = "camelCase")]
+    #[repr, serde(rename_all(deserialize = "PascalCase", serialize = "camelCase"))]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize), cfg_attr(feature = "specta", derive(specta::Type))]
+    pub struct ChargerData {
+        #[serde(alias = "ChargerID", default, deserialize_with = "lenient")]
+        pub charger_id: Option<i64>,
+        /// mA
+        #[serde(default, deserialize_with = "lenient")]
+        pub charging_current: Option<i32>,
+        /// mV
+        #[serde(default, deserialize_with = "lenient")]
+        pub charging_voltage: Option<i32>,
+        #[serde(default, deserialize_with = "lenient")]
+        pub not_charging_reason: Option<i64>,
+        #[serde(default, deserialize_with = "lenient")]
+        pub charger_inhibit_reason: Option<i64>,
+        #[serde(default, deserialize_with = "lenient")]
+        pub vac_voltage_limit: Option<i32>,
+    }
+
+    #[out, serde(rename_all = "camelCase")]
+    #[repr, serde(rename_all(deserialize = "PascalCase", serialize = "camelCase"))]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize), cfg_attr(feature = "specta", derive(specta::Type))]
     pub struct IORegistry {
         pub adapter_details: AdapterDetails,
+        #[serde(default, deserialize_with = "lenient")]
         pub power_telemetry_data: Option<PowerTelemetryData>,
         pub absolute_capacity: i32,
         pub amperage: i32,
         pub voltage: i32,
+        #[serde(default, deserialize_with = "lenient")]
         pub apple_raw_battery_voltage: Option<i32>,
         pub apple_raw_current_capacity: i32,
         pub apple_raw_max_capacity: i32,
@@ -91,6 +238,25 @@ with_repr! {
         pub time_remaining: i32,
         // TODO: check
         pub update_time: i64,
+        #[serde(default, deserialize_with = "lenient")]
+        pub battery_data: Option<BatteryData>,
+        #[serde(default, deserialize_with = "lenient")]
+        pub charger_data: Option<ChargerData>,
+        #[serde(default, deserialize_with = "lenient")]
+        pub serial: Option<String>,
+        /// As reported, the encoding differs between models
+        #[serde(default, deserialize_with = "lenient")]
+        pub manufacture_date: Option<i64>,
+        #[serde(default, deserialize_with = "lenient")]
+        pub battery_installed: Option<bool>,
+        #[serde(default, deserialize_with = "lenient")]
+        pub external_connected: Option<bool>,
+        #[serde(default, deserialize_with = "lenient")]
+        pub permanent_failure_status: Option<i64>,
+        /// Keys we don't model, as reported
+        #[serde(default)]
+        #[cfg_attr(feature = "specta", specta(skip))]
+        pub extra: BTreeMap<String, plist::Value>,
     }
 }
 
@@ -101,8 +267,440 @@ impl Deref for IORegistry {
     }
 }
 
+#[derive(Debug, Error)]
+pub enum IORegistryParseError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Failed to parse plist: {0}")]
+    Plist(#[from] plist::Error),
+
+    #[error("No AppleSmartBattery entry in the plist")]
+    NoEntry,
+}
+
 impl IORegistry {
     pub fn ptd(&self) -> Option<&PowerTelemetryData> {
         self.power_telemetry_data.as_ref()
     }
+
+    /// Parse an XML or binary plist holding any of
+    ///
+    /// - the output of `ioreg -r -c AppleSmartBattery -a`, the first entry is
+    ///   used
+    /// - a single `AppleSmartBattery` entry
+    /// - a `diagnostics_relay` `IORegistry` response
+    pub fn from_plist(bytes: &[u8]) -> Result<Self, IORegistryParseError> {
+        Self::from_reader(Cursor::new(bytes))
+    }
+
+    pub fn from_reader(reader: impl Read + Seek) -> Result<Self, IORegistryParseError> {
+        Self::from_value(&plist::Value::from_reader(reader)?)
+    }
+
+    /// Load a plist file, see [`IORegistry::from_plist`] for the formats.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, IORegistryParseError> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    pub fn from_value(value: &plist::Value) -> Result<Self, IORegistryParseError> {
+        let dict = Self::entry(value)?;
+
+        let mut registry = plist::from_value::<repr::IORegistry>(&dict.clone().into())?;
+        registry.extra = unmodelled::<repr::IORegistry>(dict);
+        if let Some(adapter) = dict.get("AdapterDetails").and_then(|v| v.as_dictionary()) {
+            registry.adapter_details.extra = unmodelled::<repr::AdapterDetails>(adapter);
+        }
+        Ok(registry.into())
+    }
+}
+
+impl IORegistry {
+    /// The `AppleSmartBattery` entry in any of the formats
+    /// [`IORegistry::from_plist`] accepts, with every key as reported.
+    pub fn entry(value: &plist::Value) -> Result<&plist::Dictionary, IORegistryParseError> {
+        let entry = match value {
+            plist::Value::Array(entries) => entries.first().ok_or(IORegistryParseError::NoEntry)?,
+            value => value,
+        };
+        let dict = entry.as_dictionary().ok_or(IORegistryParseError::NoEntry)?;
+
+        match dict.get("Diagnostics") {
+            Some(diagnostics) => diagnostics
+                .as_dictionary()
+                .and_then(|diagnostics| diagnostics.get("IORegistry"))
+                .and_then(|entry| entry.as_dictionary())
+                .ok_or(IORegistryParseError::NoEntry),
+            None => Ok(dict),
+        }
+    }
+}
+
+/// `None` for an optional field of another type than expected, so a key
+/// Apple changes doesn't fail the whole entry.
+fn lenient<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    let AnyValue(value) = AnyValue::deserialize(deserializer)?;
+    Ok(value.and_then(|value| plist::from_value(&value).ok()))
+}
+
+/// Whatever a field holds, as a plist value. Unlike [`plist::Value`] it takes
+/// nulls, which the camelCase JSON of a `None` field is, and leaves them out.
+struct AnyValue(Option<plist::Value>);
+
+impl<'de> Deserialize<'de> for AnyValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct AnyVisitor;
+
+        impl<'de> Visitor<'de> for AnyVisitor {
+            type Value = AnyValue;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("any value")
+            }
+
+            fn visit_bool<E>(self, v: bool) -> Result<AnyValue, E> {
+                Ok(AnyValue(Some(v.into())))
+            }
+
+            fn visit_i64<E>(self, v: i64) -> Result<AnyValue, E> {
+                Ok(AnyValue(Some(v.into())))
+            }
+
+            fn visit_u64<E>(self, v: u64) -> Result<AnyValue, E> {
+                Ok(AnyValue(Some(v.into())))
+            }
+
+            fn visit_f64<E>(self, v: f64) -> Result<AnyValue, E> {
+                Ok(AnyValue(Some(v.into())))
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<AnyValue, E> {
+                Ok(AnyValue(Some(v.into())))
+            }
+
+            fn visit_bytes<E>(self, v: &[u8]) -> Result<AnyValue, E> {
+                Ok(AnyValue(Some(plist::Value::Data(v.to_vec()))))
+            }
+
+            fn visit_none<E>(self) -> Result<AnyValue, E> {
+                Ok(AnyValue(None))
+            }
+
+            fn visit_unit<E>(self) -> Result<AnyValue, E> {
+                Ok(AnyValue(None))
+            }
+
+            fn visit_some<D: Deserializer<'de>>(self, d: D) -> Result<AnyValue, D::Error> {
+                AnyValue::deserialize(d)
+            }
+
+            fn visit_newtype_struct<D: Deserializer<'de>>(
+                self,
+                d: D,
+            ) -> Result<AnyValue, D::Error> {
+                AnyValue::deserialize(d)
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<AnyValue, A::Error> {
+                let mut items = Vec::new();
+                while let Some(AnyValue(item)) = seq.next_element()? {
+                    items.extend(item);
+                }
+                Ok(AnyValue(Some(plist::Value::Array(items))))
+            }
+
+            fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<AnyValue, A::Error> {
+                let mut dict = plist::Dictionary::new();
+                while let Some((key, AnyValue(value))) = map.next_entry::<String, AnyValue>()? {
+                    if let Some(value) = value {
+                        dict.insert(key, value);
+                    }
+                }
+                Ok(AnyValue(Some(plist::Value::Dictionary(dict))))
+            }
+        }
+
+        deserializer.deserialize_any(AnyVisitor)
+    }
+}
+
+/// The entries of `dict` that `T` has no field for.
+///
+/// `#[serde(flatten)]` would do this, but the plist deserializer can't hand
+/// nested dictionaries through serde's buffering.
+fn unmodelled<T: DeserializeOwned>(dict: &plist::Dictionary) -> BTreeMap<String, plist::Value> {
+    let fields = field_names::<T>();
+    dict.iter()
+        .filter(|(key, _)| !fields.contains(&key.as_str()))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+/// The keys `T` deserializes from, aliases included.
+fn field_names<T: DeserializeOwned>() -> &'static [&'static str] {
+    struct Fields<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de> Deserializer<'de> for Fields<'_> {
+        type Error = de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+            Err(de::Error::custom("not a struct"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _: &'static str,
+            fields: &'static [&'static str],
+            _: V,
+        ) -> Result<V::Value, Self::Error> {
+            *self.0 = fields;
+            Err(de::Error::custom("only after the fields"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes
+            byte_buf option unit unit_struct newtype_struct seq tuple tuple_struct map
+            enum identifier ignored_any
+        }
+    }
+
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(Fields(&mut fields));
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENTRY: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+    <key>AdapterDetails</key>
+    <dict>
+        <key>Name</key><string>96W USB-C Power Adapter</string>
+        <key>Watts</key><integer>96</integer>
+        <key>FamilyCode</key><integer>-536854528</integer>
+    </dict>
+    <key>PowerTelemetryData</key>
+    <dict>
+        <key>AdapterEfficiencyLoss</key><integer>500</integer>
+        <key>BatteryPower</key><integer>3000</integer>
+        <key>SystemCurrentIn</key><integer>900</integer>
+        <key>SystemEnergyConsumed</key><integer>123456</integer>
+        <key>SystemLoad</key><integer>8000</integer>
+        <key>SystemPowerIn</key><integer>11000</integer>
+        <key>SystemVoltageIn</key><integer>12000</integer>
+    </dict>
+    <key>AbsoluteCapacity</key><integer>2000</integer>
+    <key>Amperage</key><integer>250</integer>
+    <key>Voltage</key><integer>12500</integer>
+    <key>AppleRawCurrentCapacity</key><integer>2000</integer>
+    <key>AppleRawMaxCapacity</key><integer>4000</integer>
+    <key>CurrentCapacity</key><integer>51</integer>
+    <key>CycleCount</key><integer>42</integer>
+    <key>DesignCapacity</key><integer>4500</integer>
+    <key>FullyCharged</key><false/>
+    <key>InstantAmperage</key><integer>250</integer>
+    <key>IsCharging</key><true/>
+    <key>MaxCapacity</key><integer>100</integer>
+    <key>Temperature</key><integer>3050</integer>
+    <key>TimeRemaining</key><integer>90</integer>
+    <key>UpdateTime</key><integer>1700000000</integer>
+    <key>BatteryData</key>
+    <dict>
+        <key>CellVoltage</key><array><integer>4100</integer><integer>4110</integer></array>
+        <key>DesignCycleCount9C</key><integer>1000</integer>
+        <key>LifetimeData</key>
+        <dict>
+            <key>MaximumTemperature</key><integer>45</integer>
+        </dict>
+    </dict>
+    <key>Serial</key><string>F5D0000000000</string>
+</dict>
+</plist>"#;
+
+    #[test]
+    fn converts_nested_structs_field_by_field() {
+        let io = IORegistry::from_plist(ENTRY.as_bytes()).unwrap();
+
+        assert_eq!(
+            io.adapter_details.name.as_deref(),
+            Some("96W USB-C Power Adapter")
+        );
+        assert_eq!(io.adapter_details.watts, Some(96));
+        assert!(io.adapter_details.extra.contains_key("FamilyCode"));
+
+        let ptd = io.ptd().unwrap();
+        assert_eq!(ptd.adapter_efficiency_loss, 500);
+        assert_eq!(ptd.system_energy_consumed, 123456);
+        assert_eq!(ptd.system_load, 8000);
+
+        assert_eq!(io.voltage, 12500);
+        assert_eq!(io.apple_raw_max_capacity, 4000);
+        assert_eq!(io.current_capacity, 51);
+        assert!(io.is_charging);
+        assert!(!io.fully_charged);
+        assert_eq!(io.update_time, 1700000000);
+        assert_eq!(io.serial.as_deref(), Some("F5D0000000000"));
+
+        let battery = io.battery_data.as_ref().unwrap();
+        assert_eq!(battery.cell_voltage.as_deref(), Some(&[4100, 4110][..]));
+        assert_eq!(battery.design_cycle_count, Some(1000));
+        let lifetime = battery.lifetime_data.as_ref().unwrap();
+        assert_eq!(lifetime.maximum_temperature, Some(45));
+        assert_eq!(lifetime.minimum_temperature, None);
+        assert!(io.charger_data.is_none());
+    }
+
+    #[test]
+    fn serializes_as_camel_case() {
+        let io = IORegistry::from_plist(ENTRY.as_bytes()).unwrap();
+        let json = serde_json::to_value(&io).unwrap();
+
+        assert_eq!(json["appleRawMaxCapacity"], 4000);
+        assert_eq!(json["powerTelemetryData"]["systemLoad"], 8000);
+        assert_eq!(json["batteryData"]["designCycleCount"], 1000);
+    }
+
+    fn entry() -> plist::Value {
+        plist::Value::from_reader_xml(ENTRY.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn parses_ioreg_output() {
+        let mut out = Vec::new();
+        plist::Value::Array(vec![entry(), plist::Value::Dictionary(Default::default())])
+            .to_writer_xml(&mut out)
+            .unwrap();
+
+        let io = IORegistry::from_plist(&out).unwrap();
+        assert_eq!(io.cycle_count, 42);
+    }
+
+    #[test]
+    fn parses_binary_plists() {
+        let mut out = Vec::new();
+        entry().to_writer_binary(&mut out).unwrap();
+
+        let io = IORegistry::from_reader(Cursor::new(out)).unwrap();
+        assert_eq!(io.cycle_count, 42);
+        assert_eq!(io.ptd().unwrap().system_power_in, 11000);
+    }
+
+    #[test]
+    fn parses_diagnostics_relay_responses() {
+        let mut diagnostics = plist::Dictionary::new();
+        diagnostics.insert("IORegistry".into(), entry());
+        let mut response = plist::Dictionary::new();
+        response.insert("Diagnostics".into(), diagnostics.into());
+        response.insert("Status".into(), "Success".into());
+
+        let io = IORegistry::from_value(&response.into()).unwrap();
+        assert_eq!(io.cycle_count, 42);
+        assert!(!io.extra.contains_key("Status"));
+    }
+
+    #[test]
+    fn refuses_what_holds_no_entry() {
+        let empty = plist::Value::Array(vec![]);
+        assert!(matches!(
+            IORegistry::from_value(&empty),
+            Err(IORegistryParseError::NoEntry)
+        ));
+
+        let mut response = plist::Dictionary::new();
+        response.insert("Diagnostics".into(), plist::Dictionary::new().into());
+        assert!(matches!(
+            IORegistry::from_value(&response.into()),
+            Err(IORegistryParseError::NoEntry)
+        ));
+
+        assert!(matches!(
+            IORegistry::from_plist(b"not a plist"),
+            Err(IORegistryParseError::Plist(_))
+        ));
+    }
+
+    #[test]
+    fn tolerates_mistyped_optional_fields() {
+        let mistyped = ENTRY
+            .replace(
+                "<key>Serial</key><string>F5D0000000000</string>",
+                "<key>Serial</key><integer>5</integer>",
+            )
+            .replace(
+                "<key>Watts</key><integer>96</integer>",
+                "<key>Watts</key><data>AAEC</data>",
+            )
+            .replace(
+                "<key>Name</key><string>96W USB-C Power Adapter</string>",
+                "<key>Name</key><array/>",
+            )
+            .replace(
+                "<key>MaximumTemperature</key><integer>45</integer>",
+                "<key>MaximumTemperature</key><real>45.5</real>",
+            )
+            .replace(
+                "<key>DesignCycleCount9C</key><integer>1000</integer>",
+                "<key>DesignCycleCount9C</key><dict/>",
+            );
+        let io = IORegistry::from_plist(mistyped.as_bytes()).unwrap();
+
+        assert_eq!(io.serial, None);
+        assert_eq!(io.adapter_details.watts, None);
+        assert_eq!(io.adapter_details.name, None);
+        assert!(io.adapter_details.extra.contains_key("FamilyCode"));
+        let battery = io.battery_data.as_ref().unwrap();
+        assert_eq!(battery.design_cycle_count, None);
+        assert_eq!(
+            battery.lifetime_data.as_ref().unwrap().maximum_temperature,
+            None
+        );
+        assert_eq!(battery.cell_voltage.as_deref(), Some(&[4100, 4110][..]));
+        assert_eq!(io.cycle_count, 42);
+
+        // a mistyped required field still fails
+        let broken = ENTRY.replace(
+            "<key>CycleCount</key><integer>42</integer>",
+            "<key>CycleCount</key><string>42</string>",
+        );
+        assert!(IORegistry::from_plist(broken.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let io = IORegistry::from_plist(ENTRY.as_bytes()).unwrap();
+        let json = serde_json::to_string(&io).unwrap();
+        let io: IORegistry = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(io.serial.as_deref(), Some("F5D0000000000"));
+        assert_eq!(io.adapter_details.watts, Some(96));
+        assert_eq!(io.battery_data.unwrap().design_cycle_count, Some(1000));
+        assert!(io.charger_data.is_none());
+    }
+
+    #[test]
+    fn finds_the_raw_entry() {
+        let mut diagnostics = plist::Dictionary::new();
+        diagnostics.insert("IORegistry".into(), entry());
+        let mut response = plist::Dictionary::new();
+        response.insert("Diagnostics".into(), diagnostics.into());
+
+        let response = plist::Value::from(response);
+        let raw = IORegistry::entry(&response).unwrap();
+        assert!(raw.contains_key("CycleCount"));
+        assert!(raw.contains_key("AdapterDetails"));
+
+        let mut malformed = plist::Dictionary::new();
+        malformed.insert("Diagnostics".into(), "IORegistry".into());
+        assert!(matches!(
+            IORegistry::entry(&malformed.into()),
+            Err(IORegistryParseError::NoEntry)
+        ));
+    }
 }
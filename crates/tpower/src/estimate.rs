@@ -0,0 +1,340 @@
+//! Time to full and time to empty from a smoothed charge rate.
+//!
+//! The rate is the battery power over the battery voltage, or the change of
+//! the remaining capacity where no power is reported, as on iOS devices.
+//! Charging slows down once the charger switches to constant voltage, from
+//! [`EstimatorConfig::taper_start`] on the current is modelled to decay
+//! exponentially until the battery is full.
+
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::provider::NormalizedResource;
+
+/// The charger terminates once this share of the full capacity is left.
+const TAPER_END: f64 = 0.01;
+/// Below this the battery is considered idle, mA.
+const MIN_RATE: f64 = 1.;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EstimatorConfig {
+    /// How long it takes for a sample to lose half its weight
+    pub half_life: Duration,
+    /// Battery level in percent where constant voltage charging starts
+    pub taper_start: f32,
+    /// Samples until the smoothed rate is fully trusted
+    pub warmup: u32,
+}
+
+impl Default for EstimatorConfig {
+    fn default() -> Self {
+        Self {
+            half_life: Duration::from_secs(120),
+            taper_start: 80.,
+            warmup: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[serde(rename_all = "camelCase")]
+pub enum EstimateKind {
+    ToFull,
+    ToEmpty,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[serde(rename_all = "camelCase")]
+pub struct Estimate {
+    pub kind: EstimateKind,
+    pub remaining: Duration,
+    /// Smoothed charge or discharge current, mA
+    pub rate: f32,
+    /// 0 to 1, low while the rate settles or swings
+    pub confidence: f32,
+}
+
+/// Exponentially weighted mean and variance.
+#[derive(Debug, Clone, Copy, Default)]
+struct Ewma {
+    mean: f64,
+    variance: f64,
+    samples: u32,
+}
+
+impl Ewma {
+    fn push(&mut self, value: f64, alpha: f64) {
+        if self.samples == 0 {
+            self.mean = value;
+        } else {
+            let diff = value - self.mean;
+            self.mean += alpha * diff;
+            self.variance = (1. - alpha) * (self.variance + alpha * diff * diff);
+        }
+        self.samples = self.samples.saturating_add(1);
+    }
+
+    /// Coefficient of variation.
+    fn variation(&self) -> f64 {
+        if self.mean > 0. {
+            self.variance.sqrt() / self.mean
+        } else {
+            f64::INFINITY
+        }
+    }
+}
+
+/// Estimates for one battery, fed one sample at a time.
+#[derive(Debug, Clone, Default)]
+pub struct Estimator {
+    config: EstimatorConfig,
+    charging: Option<bool>,
+    rate: Ewma,
+    /// When `rate` was last fed
+    last: Option<SystemTime>,
+    capacity: Option<i32>,
+    /// When `capacity` last changed
+    capacity_changed: Option<SystemTime>,
+    derived: bool,
+}
+
+impl Estimator {
+    pub fn new(config: EstimatorConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn config(&self) -> &EstimatorConfig {
+        &self.config
+    }
+
+    /// Forget the rate, e.g. after the device was away for a while.
+    pub fn reset(&mut self) {
+        *self = Self::new(self.config);
+    }
+
+    /// Feed a sample taken at `now`. `None` while on external power without
+    /// charging, or before there is a rate to go by.
+    pub fn update(&mut self, res: &NormalizedResource, now: SystemTime) -> Option<Estimate> {
+        if self.charging != Some(res.is_charging) {
+            self.reset();
+            self.charging = Some(res.is_charging);
+        }
+        // held on the adapter
+        if !res.is_charging && !res.is_discharging() {
+            return None;
+        }
+
+        let measured = (res.voltage > 0. && res.battery_power.abs() > 0.)
+            .then(|| (res.battery_power.abs() / res.voltage * 1000.) as f64);
+        let capacity_rate = self.capacity_rate(res.current_capacity, now);
+        if let Some(rate) = measured.or(capacity_rate) {
+            self.derived = measured.is_none();
+            let elapsed = self
+                .last
+                .replace(now)
+                .and_then(|last| now.duration_since(last).ok())
+                .unwrap_or_default();
+            let half_lives = elapsed.as_secs_f64() / self.config.half_life.as_secs_f64();
+            self.rate.push(rate, 1. - 0.5f64.powf(half_lives));
+        }
+
+        let rate = self.rate.mean;
+        if self.rate.samples == 0 || rate < MIN_RATE || res.max_capacity <= 0 {
+            return None;
+        }
+
+        let current = res.current_capacity.clamp(0, res.max_capacity) as f64;
+        let full = res.max_capacity as f64;
+        let (kind, hours, tapering) = if res.is_charging {
+            let (hours, tapering) = self.hours_to_full(current, full, rate);
+            (EstimateKind::ToFull, hours, tapering)
+        } else {
+            (EstimateKind::ToEmpty, current / rate, false)
+        };
+
+        let warmup = (self.rate.samples as f64 / self.config.warmup.max(1) as f64).min(1.);
+        let stability = 1. / (1. + self.rate.variation());
+        let model = if tapering { 0.8 } else { 1. };
+        let source = if self.derived { 0.7 } else { 1. };
+
+        Some(Estimate {
+            kind,
+            remaining: Duration::from_secs_f64(hours.max(0.) * 3600.),
+            rate: rate as f32,
+            confidence: (warmup * stability * model * source).clamp(0., 1.) as f32,
+        })
+    }
+
+    /// Hours until `full` mAh at `rate` mA, and whether the taper is part of
+    /// it.
+    fn hours_to_full(&self, current: f64, full: f64, rate: f64) -> (f64, bool) {
+        let taper_start = full * self.config.taper_start.clamp(0., 100.) as f64 / 100.;
+        // constant current up to the taper, the rate at its start is then
+        // the constant current one
+        let constant = (taper_start - current).max(0.) / rate;
+
+        let left = full - current.max(taper_start);
+        let end = full * TAPER_END;
+        // remaining charge decays with a time constant of left / rate
+        let taper = if left > end {
+            left / rate * (left / end).ln()
+        } else {
+            left / rate
+        };
+        (constant + taper, left > 0.)
+    }
+
+    /// mA from how fast the remaining capacity moves, the first change
+    /// after a reset only sets the reference.
+    fn capacity_rate(&mut self, capacity: i32, now: SystemTime) -> Option<f64> {
+        let last = self.capacity.replace(capacity)?;
+        if capacity == last {
+            return None;
+        }
+        let since = self.capacity_changed.replace(now)?;
+
+        let hours = now.duration_since(since).ok()?.as_secs_f64() / 3600.;
+        (hours > 0.).then(|| (capacity - last).abs() as f64 / hours)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::NormalizedData;
+
+    /// A 5000 mAh battery at `capacity`, moving `battery_power` W at 12 V.
+    fn sample(is_charging: bool, capacity: i32, battery_power: f32) -> NormalizedResource {
+        NormalizedResource {
+            is_charging,
+            adapter_name: is_charging.then(|| "USB-C".to_string()),
+            current_capacity: capacity,
+            max_capacity: 5000,
+            voltage: 12.,
+            data: NormalizedData {
+                battery_power,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000 + secs)
+    }
+
+    fn hours(estimate: &Estimate) -> f64 {
+        estimate.remaining.as_secs_f64() / 3600.
+    }
+
+    #[test]
+    fn tapers_the_time_to_full() {
+        // 12 W at 12 V is 1000 mA
+        let estimate = Estimator::default()
+            .update(&sample(true, 2000, 12.), at(0))
+            .unwrap();
+        assert_eq!(estimate.kind, EstimateKind::ToFull);
+        assert_eq!(estimate.rate, 1000.);
+        // 2 h of constant current up to 80%, then 1000 mAh decaying with a
+        // time constant of 1 h down to 1% of the capacity
+        let expected = 2. + 20f64.ln();
+        assert!((hours(&estimate) - expected).abs() < 1e-3);
+
+        let estimate = Estimator::default()
+            .update(&sample(true, 4500, 12.), at(0))
+            .unwrap();
+        assert!((hours(&estimate) - 0.5 * 10f64.ln()).abs() < 1e-3);
+
+        let estimate = Estimator::default()
+            .update(&sample(true, 5000, 12.), at(0))
+            .unwrap();
+        assert_eq!(estimate.remaining, Duration::ZERO);
+    }
+
+    #[test]
+    fn estimates_the_time_to_empty() {
+        let mut estimator = Estimator::default();
+        let estimate = estimator.update(&sample(false, 3000, 24.), at(0)).unwrap();
+
+        assert_eq!(estimate.kind, EstimateKind::ToEmpty);
+        assert_eq!(estimate.rate, 2000.);
+        assert!((hours(&estimate) - 1.5).abs() < 1e-3);
+        // a single sample is barely trusted
+        assert!((estimate.confidence - 0.1).abs() < 1e-6);
+
+        let mut estimate = estimate;
+        for i in 1..20 {
+            estimate = estimator
+                .update(&sample(false, 3000, 24.), at(i * 10))
+                .unwrap();
+        }
+        assert!((estimate.confidence - 1.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn trusts_a_derived_rate_less() {
+        let mut measured = Estimator::default();
+        let mut derived = Estimator::default();
+        let mut estimates = (None, None);
+        // 10 mAh a minute, 600 mA
+        for (i, capacity) in [3000, 2990, 2980].into_iter().enumerate() {
+            let now = at(i as u64 * 60);
+            estimates = (
+                measured.update(&sample(false, capacity, 7.2), now),
+                derived.update(&sample(false, capacity, 0.), now),
+            );
+            // the first two changes only set the reference
+            if i < 2 {
+                assert_eq!(estimates.1, None);
+            }
+        }
+        let (Some(measured), Some(derived)) = estimates else {
+            panic!("both estimate after three samples");
+        };
+
+        assert!((derived.rate - 600.).abs() < 1e-3);
+        assert!((measured.rate - 600.).abs() < 1e-3);
+        assert!((hours(&derived) - 2980. / 600.).abs() < 1e-3);
+        assert!((derived.confidence - 0.7 * 0.1).abs() < 1e-6);
+        assert!((measured.confidence - 0.3).abs() < 1e-6);
+    }
+
+    #[test]
+    fn resets_when_charging_flips() {
+        let mut estimator = Estimator::default();
+        for i in 0..10 {
+            estimator.update(&sample(false, 3000, 24.), at(i * 10));
+        }
+
+        let estimate = estimator.update(&sample(true, 3000, 6.), at(100)).unwrap();
+        assert_eq!(estimate.kind, EstimateKind::ToFull);
+        // nothing of the discharge is left in the rate
+        assert_eq!(estimate.rate, 500.);
+        assert!((estimate.confidence - 0.1 * 0.8).abs() < 1e-6);
+    }
+
+    #[test]
+    fn gives_nothing_while_held_on_the_adapter() {
+        let mut estimator = Estimator::default();
+        let mut held = sample(false, 4000, 0.);
+        held.adapter_name = Some("USB-C".to_string());
+
+        assert_eq!(estimator.update(&held, at(0)), None);
+        assert_eq!(estimator.update(&sample(false, 4000, 0.), at(10)), None);
+
+        // an adapter the battery reports without a name, while the gauge
+        // still shows some drain
+        let mut held = sample(false, 4000, 6.);
+        held.external_connected = Some(true);
+        assert_eq!(estimator.update(&held, at(20)), None);
+        assert_eq!(estimator.update(&held, at(30)), None);
+        held.external_connected = Some(false);
+        assert!(estimator.update(&held, at(40)).is_some());
+    }
+}
@@ -0,0 +1,235 @@
+//! Render samples for scripts and other tools.
+//!
+//! Field names are taken from the serde layout of [`NormalizedResource`],
+//! the same camelCase names the app's events use.
+
+use std::fmt::Write;
+
+use indexmap::IndexSet;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::provider::{NormalizedResource, PowerDataFrom};
+
+mod server;
+
+pub use server::{Metrics, MetricsServer};
+
+pub const METRIC_PREFIX: &str = "tpower";
+
+/// Columns identifying the source of a sample, always in this order.
+pub const LABELS: [&str; 4] = ["source", "udid", "name", "interface"];
+
+pub fn labels(from: &PowerDataFrom) -> [(&'static str, String); 4] {
+    let (source, udid, name, interface) = match from {
+        PowerDataFrom::Local => ("local", "", "", String::new()),
+        PowerDataFrom::Remote((udid, name, interface)) => (
+            "remote",
+            udid.as_str(),
+            name.as_str(),
+            format!("{interface:?}"),
+        ),
+    };
+    [
+        (LABELS[0], source.to_string()),
+        (LABELS[1], udid.to_string()),
+        (LABELS[2], name.to_string()),
+        (LABELS[3], interface),
+    ]
+}
+
+/// `value` as a JSON object.
+///
+/// Goes through the serialized text rather than [`serde_json::to_value`],
+/// which widens `f32`s and would print `22.8` as `22.799999237060547`.
+fn to_object(value: &impl Serialize) -> Map<String, Value> {
+    serde_json::to_string(value)
+        .and_then(|json| serde_json::from_str(&json))
+        .unwrap_or_default()
+}
+
+/// The fields of `value` with nested objects flattened into `parent.child`
+/// keys, e.g. `timeRemain.secs`, and arrays into `parent.index` keys, e.g.
+/// `thermal.fans.0.actual`.
+pub fn fields(value: &impl Serialize) -> Vec<(String, Value)> {
+    fn flatten(prefix: Option<&str>, value: Value, out: &mut Vec<(String, Value)>) {
+        let entries: Vec<(String, Value)> = match value {
+            Value::Object(map) => map.into_iter().collect(),
+            Value::Array(items) if prefix.is_some() => items
+                .into_iter()
+                .enumerate()
+                .map(|(i, item)| (i.to_string(), item))
+                .collect(),
+            value => {
+                if let Some(prefix) = prefix {
+                    out.push((prefix.to_string(), value));
+                }
+                return;
+            }
+        };
+        for (key, value) in entries {
+            let key = match prefix {
+                Some(prefix) => format!("{prefix}.{key}"),
+                None => key,
+            };
+            flatten(Some(&key), value, out);
+        }
+    }
+
+    let mut out = Vec::new();
+    flatten(None, Value::Object(to_object(value)), &mut out);
+    out
+}
+
+/// Every key of `rows`, in the order they are first seen.
+///
+/// Samples don't all have the same fields, e.g. only local ones carry
+/// `thermal`, so rows are matched up by key rather than by position.
+fn columns<'a>(rows: impl IntoIterator<Item = &'a [(String, Value)]>) -> Vec<&'a str> {
+    let mut keys = IndexSet::new();
+    for row in rows {
+        keys.extend(row.iter().map(|(key, _)| key.as_str()));
+    }
+    keys.into_iter().collect()
+}
+
+fn lookup<'a>(row: &'a [(String, Value)], key: &str) -> Option<&'a Value> {
+    row.iter().find(|(k, _)| k == key).map(|(_, value)| value)
+}
+
+/// One object per sample, the labels followed by the resource as the app
+/// would emit it.
+pub fn to_json(samples: &[(PowerDataFrom, NormalizedResource)]) -> Value {
+    samples
+        .iter()
+        .map(|(from, res)| {
+            let mut object = labels(from)
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), Value::String(value)))
+                .collect::<Map<_, _>>();
+            object.extend(to_object(res));
+            Value::Object(object)
+        })
+        .collect()
+}
+
+pub fn to_csv(samples: &[(PowerDataFrom, NormalizedResource)]) -> String {
+    fn cell(value: &str) -> String {
+        if value.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    }
+
+    if samples.is_empty() {
+        return String::new();
+    }
+
+    let rows = samples
+        .iter()
+        .map(|(from, res)| (from, fields(res)))
+        .collect::<Vec<_>>();
+    let columns = columns(rows.iter().map(|(_, fields)| fields.as_slice()));
+
+    let header = LABELS
+        .iter()
+        .chain(&columns)
+        .map(|key| cell(key))
+        .collect::<Vec<_>>();
+
+    let mut out = header.join(",");
+    out.push('\n');
+    for (from, fields) in &rows {
+        let row = labels(from)
+            .into_iter()
+            .map(|(_, value)| value)
+            .chain(columns.iter().map(|key| match lookup(fields, key) {
+                None | Some(Value::Null) => String::new(),
+                Some(Value::String(s)) => s.clone(),
+                Some(value) => value.to_string(),
+            }))
+            .map(|value| cell(&value))
+            .collect::<Vec<_>>();
+        out.push_str(&row.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+/// `timeRemain.secs` -> `tpower_time_remain_secs`
+pub fn metric_name(field: &str) -> String {
+    let mut name = format!("{METRIC_PREFIX}_");
+    for c in field.chars() {
+        match c {
+            c if c.is_ascii_uppercase() => {
+                name.push('_');
+                name.push(c.to_ascii_lowercase());
+            }
+            c if c.is_ascii_alphanumeric() => name.push(c),
+            _ => name.push('_'),
+        }
+    }
+    name
+}
+
+/// Render `{key="value",...}` for a metric line.
+pub fn metric_labels<'a>(labels: impl IntoIterator<Item = (&'a str, &'a str)>) -> String {
+    let labels = labels
+        .into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(key, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{key}=\"{value}\"")
+        })
+        .collect::<Vec<_>>();
+    format!("{{{}}}", labels.join(","))
+}
+
+/// Prometheus text exposition format, one gauge per numeric field.
+///
+/// String fields such as `adapterName` have no numeric value and are left out.
+pub fn to_prometheus(samples: &[(PowerDataFrom, NormalizedResource)]) -> String {
+    let rows = samples
+        .iter()
+        .map(|(from, res)| (label_set(from), fields(res)))
+        .collect::<Vec<_>>();
+
+    let mut out = String::new();
+    write_gauges(&mut out, &rows);
+    out
+}
+
+fn label_set(from: &PowerDataFrom) -> String {
+    let labels = labels(from);
+    metric_labels(labels.iter().map(|(k, v)| (*k, v.as_str())))
+}
+
+/// Write a gauge for every numeric field, one line per row that has it.
+fn write_gauges(out: &mut String, rows: &[(String, Vec<(String, Value)>)]) {
+    for key in columns(rows.iter().map(|(_, fields)| fields.as_slice())) {
+        let mut lines = rows
+            .iter()
+            .filter_map(|(labels, fields)| {
+                let value = match lookup(fields, key)? {
+                    Value::Number(n) => n.as_f64()?,
+                    Value::Bool(b) => f64::from(u8::from(*b)),
+                    _ => return None,
+                };
+                Some((labels, value))
+            })
+            .peekable();
+        if lines.peek().is_none() {
+            continue;
+        }
+
+        let name = metric_name(key);
+        let _ = writeln!(out, "# TYPE {name} gauge");
+        for (labels, value) in lines {
+            let _ = writeln!(out, "{name}{labels} {value}");
+        }
+    }
+}
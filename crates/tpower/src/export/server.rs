@@ -0,0 +1,327 @@
+//! A tiny HTTP endpoint serving [`Metrics`] for Prometheus to scrape.
+
+use std::{
+    fmt::Write as _,
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use indexmap::IndexMap;
+use serde_json::Value;
+
+use super::{fields, label_set, metric_name, write_gauges};
+use crate::provider::{NormalizedResource, PowerDataFrom};
+
+/// Samples further apart than this are treated as a gap, e.g. the machine
+/// was asleep, and not integrated into the energy counters.
+const MAX_SAMPLE_GAP: Duration = Duration::from_secs(60);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+const MAX_HEADER_LINES: usize = 100;
+
+struct SourceMetrics {
+    last: NormalizedResource,
+    updated: Instant,
+    /// Joules used by the system.
+    system_energy: f64,
+    /// Joules drawn from the adapter.
+    input_energy: f64,
+}
+
+type Counter = (&'static str, &'static str, fn(&SourceMetrics) -> f64);
+
+const COUNTERS: [Counter; 3] = [
+    ("cycleCount", "Battery charge cycles.", |m| {
+        m.last.cycle_count as f64
+    }),
+    (
+        "systemEnergyJoules",
+        "Energy used by the system since the exporter started.",
+        |m| m.system_energy,
+    ),
+    (
+        "inputEnergyJoules",
+        "Energy drawn from the adapter since the exporter started.",
+        |m| m.input_energy,
+    ),
+];
+
+/// The latest sample of every source, plus energy counters integrated from
+/// the samples seen so far.
+#[derive(Default)]
+pub struct Metrics {
+    sources: Mutex<IndexMap<PowerDataFrom, SourceMetrics>>,
+}
+
+impl Metrics {
+    pub fn update(&self, from: &PowerDataFrom, res: &NormalizedResource) {
+        self.update_at(from, res, Instant::now());
+    }
+
+    fn update_at(&self, from: &PowerDataFrom, res: &NormalizedResource, now: Instant) {
+        let mut sources = self.sources.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let Some(metrics) = sources.get_mut(from) else {
+            sources.insert(from.clone(), SourceMetrics {
+                last: res.clone(),
+                updated: now,
+                system_energy: 0.,
+                input_energy: 0.,
+            });
+            return;
+        };
+
+        let elapsed = now - metrics.updated;
+        if elapsed <= MAX_SAMPLE_GAP {
+            let secs = elapsed.as_secs_f64();
+            metrics.system_energy += trapezoid(metrics.last.system_load, res.system_load, secs);
+            metrics.input_energy += trapezoid(metrics.last.system_in, res.system_in, secs);
+        }
+        metrics.last = res.clone();
+        metrics.updated = now;
+    }
+
+    /// Stop exporting `from`, e.g. once a device is detached.
+    pub fn remove(&self, from: &PowerDataFrom) {
+        self.sources
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .shift_remove(from);
+    }
+
+    /// Gauges for every [`NormalizedData`](crate::provider::NormalizedData)
+    /// and [`ThermalData`](crate::ffi::smc::ThermalData) field and the
+    /// counters, in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let sources = self.sources.lock().unwrap_or_else(PoisonError::into_inner);
+        let rows = sources
+            .iter()
+            .map(|(from, metrics)| (label_set(from), metrics))
+            .collect::<Vec<_>>();
+
+        let mut out = String::new();
+        write_gauges(
+            &mut out,
+            &rows
+                .iter()
+                .map(|(labels, metrics)| (labels.clone(), gauges(&metrics.last)))
+                .collect::<Vec<_>>(),
+        );
+
+        if rows.is_empty() {
+            return out;
+        }
+        for (field, help, value) in COUNTERS {
+            let name = format!("{}_total", metric_name(field));
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} counter");
+            for (labels, metrics) in &rows {
+                let _ = writeln!(out, "{name}{labels} {}", value(metrics));
+            }
+        }
+        out
+    }
+}
+
+fn gauges(res: &NormalizedResource) -> Vec<(String, Value)> {
+    let thermal = fields(&res.thermal)
+        .into_iter()
+        .map(|(key, value)| (format!("thermal.{key}"), value));
+    fields(&res.data).into_iter().chain(thermal).collect()
+}
+
+fn trapezoid(from: f32, to: f32, secs: f64) -> f64 {
+    (from as f64 + to as f64) / 2. * secs
+}
+
+/// Serves [`Metrics`] at `/metrics` on a background thread until dropped.
+pub struct MetricsServer {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MetricsServer {
+    pub fn bind(addr: impl ToSocketAddrs, metrics: Arc<Metrics>) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        // polled so the thread notices when the server is dropped
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let handle = thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        if let Err(err) = respond(stream, &metrics) {
+                            log::debug!("Failed to answer metrics request: {err}");
+                        }
+                    }
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                        thread::sleep(POLL_INTERVAL)
+                    }
+                    Err(err) => {
+                        log::warn!("Failed to accept metrics connection: {err}");
+                        thread::sleep(POLL_INTERVAL);
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            addr,
+            stop,
+            handle: Some(handle),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn respond(stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+    // accepted sockets inherit non-blocking mode on macOS
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+    let mut reader = BufReader::new(&stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+
+    // the headers are of no interest, but have to be read before answering
+    let mut line = String::new();
+    for _ in 0..MAX_HEADER_LINES {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request.split_whitespace();
+    let method = parts.next();
+    let path = parts.next().and_then(|p| p.split('?').next());
+    let (status, content_type, body) = match (method, path) {
+        (Some("GET"), Some("/metrics")) => (
+            "200 OK",
+            "text/plain; version=0.0.4; charset=utf-8",
+            metrics.render(),
+        ),
+        (Some("GET"), Some("/")) => (
+            "200 OK",
+            "text/plain; charset=utf-8",
+            "tpower exporter, metrics are served at /metrics\n".to_string(),
+        ),
+        (Some("GET"), _) => (
+            "404 Not Found",
+            "text/plain; charset=utf-8",
+            "not found\n".to_string(),
+        ),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain; charset=utf-8",
+            "method not allowed\n".to_string(),
+        ),
+    };
+
+    write!(
+        &stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    (&stream).flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ffi::InterfaceType, provider::NormalizedData};
+
+    fn sample(system_load: f32, system_in: f32) -> NormalizedResource {
+        NormalizedResource {
+            cycle_count: 42,
+            data: NormalizedData {
+                system_load,
+                system_in,
+                battery_level: 80,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn renders_gauges_and_integrated_counters() {
+        let metrics = Metrics::default();
+        let local = PowerDataFrom::Local;
+        let start = Instant::now();
+        metrics.update_at(&local, &sample(10., 20.), start);
+        metrics.update_at(&local, &sample(30., 40.), start + Duration::from_secs(2));
+
+        let text = metrics.render();
+        assert!(text.contains("# TYPE tpower_system_load gauge\n"));
+        assert!(text.contains("tpower_system_load{source=\"local\"} 30\n"));
+        assert!(text.contains("tpower_battery_level{source=\"local\"} 80\n"));
+        assert!(text.contains("# TYPE tpower_system_energy_joules_total counter\n"));
+        assert!(text.contains("tpower_cycle_count_total{source=\"local\"} 42\n"));
+        // (10 + 30) / 2 W for 2 s, and (20 + 40) / 2 W
+        assert!(text.contains("tpower_system_energy_joules_total{source=\"local\"} 40\n"));
+        assert!(text.contains("tpower_input_energy_joules_total{source=\"local\"} 60\n"));
+    }
+
+    #[test]
+    fn skips_gaps_and_keeps_sources_apart() {
+        let metrics = Metrics::default();
+        let local = PowerDataFrom::Local;
+        let remote = PowerDataFrom::Remote(("udid".into(), "iPhone".into(), InterfaceType::USB));
+        let start = Instant::now();
+        metrics.update_at(&local, &sample(10., 0.), start);
+        metrics.update_at(&remote, &sample(5., 0.), start);
+        metrics.update_at(&local, &sample(10., 0.), start + MAX_SAMPLE_GAP * 2);
+        metrics.update_at(&remote, &sample(5., 0.), start + Duration::from_secs(4));
+
+        let text = metrics.render();
+        assert!(text.contains("tpower_system_energy_joules_total{source=\"local\"} 0\n"));
+        assert!(text.contains(
+            "tpower_system_energy_joules_total{source=\"remote\",udid=\"udid\",name=\"iPhone\",interface=\"USB\"} 20\n"
+        ));
+
+        metrics.remove(&remote);
+        assert!(!metrics.render().contains("remote"));
+        assert!(Metrics::default().render().is_empty());
+    }
+
+    #[test]
+    fn survives_a_poisoned_lock() {
+        let metrics = Arc::new(Metrics::default());
+        let poisoner = metrics.clone();
+        let _ = thread::spawn(move || {
+            let _guard = poisoner.sources.lock().unwrap();
+            panic!("poison the lock");
+        })
+        .join();
+        assert!(metrics.sources.is_poisoned());
+
+        metrics.update(&PowerDataFrom::Local, &sample(10., 0.));
+        assert!(metrics
+            .render()
+            .contains("tpower_system_load{source=\"local\"} 10\n"));
+    }
+}
@@ -1,5 +1,6 @@
 use std::marker::{PhantomData, PhantomPinned};
 
+#[cfg(apple_ffi)]
 use core_foundation::{
     array::CFArrayRef, dictionary::CFDictionaryRef, propertylist::CFPropertyListFormat,
     string::CFStringRef,
@@ -7,8 +8,10 @@ use core_foundation::{
 use libc::{c_char, c_void};
 
 pub mod smc;
+#[cfg(apple_ffi)]
 pub mod wrapper;
 
+#[cfg(apple_ffi)]
 pub use core_foundation;
 
 #[repr(C)]
@@ -85,21 +88,22 @@ unsafe impl Sync for AMDServiceConnection {}
 
 pub type AMDServiceConnectionRef = *const AMDServiceConnection;
 
+#[cfg(apple_ffi)]
 type AMDeviceNotificationCallback =
     extern "C" fn(_: *const AMDeviceNotificationCallbackInfo, _: *mut c_void);
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-#[cfg_attr(
-    feature = "specta",
-    derive(specta::Type, serde::Deserialize, serde::Serialize)
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Deserialize, serde::Serialize,
 )]
+#[cfg_attr(feature = "specta", derive(specta::Type))]
 pub enum InterfaceType {
     Unknown = 0,
     USB = 1,
     WiFi = 2,
 }
 
+#[cfg(apple_ffi)]
 #[link(name = "MobileDevice", kind = "framework")]
 extern "C" {
     pub fn AMDCreateDeviceList() -> CFArrayRef;
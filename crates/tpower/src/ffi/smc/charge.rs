@@ -0,0 +1,232 @@
+//! Holding off battery charging through the SMC.
+//!
+//! Recent Apple Silicon firmware has `CHTE`, older firmware and Intel
+//! machines use `CH0B` and `CH0C`, where `0x02` inhibits charging.
+
+use super::{SMCConnection, SMCError, SMCTransport};
+use crate::charge::ChargeState;
+
+const INHIBIT_KEYS: [&str; 2] = ["CH0B", "CH0C"];
+const INHIBIT: u8 = 0x02;
+
+/// Applies [`ChargeState`]s to the SMC, charging is allowed again when this
+/// is dropped.
+pub struct ChargeInhibitor<T: SMCTransport> {
+    smc: SMCConnection<T>,
+    inhibited: bool,
+    #[cfg(apple_ffi)]
+    restore_at_exit: bool,
+}
+
+#[cfg(apple_ffi)]
+impl ChargeInhibitor<super::IOKitTransport> {
+    /// Drive the charging of this Mac.
+    ///
+    /// Charging is also allowed again when the process exits without
+    /// dropping this, e.g. through [`std::process::exit`].
+    pub fn open() -> Result<Self, SMCError> {
+        let mut inhibitor = Self::new(SMCConnection::new("AppleSMC")?);
+        inhibitor.restore_at_exit = true;
+        Ok(inhibitor)
+    }
+}
+
+impl<T: SMCTransport> ChargeInhibitor<T> {
+    pub fn new(smc: SMCConnection<T>) -> Self {
+        Self {
+            smc,
+            inhibited: false,
+            #[cfg(apple_ffi)]
+            restore_at_exit: false,
+        }
+    }
+
+    pub fn smc(&self) -> &SMCConnection<T> {
+        &self.smc
+    }
+
+    pub fn is_inhibited(&self) -> bool {
+        self.inhibited
+    }
+
+    /// Drive the SMC into `state`.
+    ///
+    /// Charging counts as inhibited as soon as an inhibit is attempted and
+    /// until allowing it succeeded, so a write failing halfway through is
+    /// still undone on drop or exit.
+    pub fn apply(&mut self, state: ChargeState) -> Result<(), SMCError> {
+        let inhibit = state == ChargeState::Inhibited;
+        if inhibit {
+            self.track(true);
+        }
+        set_inhibit(&mut self.smc, inhibit)?;
+        if !inhibit {
+            self.track(false);
+        }
+        Ok(())
+    }
+
+    /// Leave charging to the system again, also clears an inhibit left
+    /// behind by an earlier process.
+    pub fn restore(&mut self) -> Result<(), SMCError> {
+        self.apply(ChargeState::Allowed)
+    }
+
+    fn track(&mut self, inhibited: bool) {
+        self.inhibited = inhibited;
+        #[cfg(apple_ffi)]
+        if self.restore_at_exit {
+            exit_guard::track(inhibited);
+        }
+    }
+}
+
+impl<T: SMCTransport> Drop for ChargeInhibitor<T> {
+    fn drop(&mut self) {
+        if !self.inhibited {
+            return;
+        }
+        if let Err(err) = self.restore() {
+            log::error!("Failed to allow charging again: {err}");
+        }
+    }
+}
+
+fn set_inhibit<T: SMCTransport>(smc: &mut SMCConnection<T>, inhibit: bool) -> Result<(), SMCError> {
+    let value = |size: u32, on: u8| {
+        let mut bytes = vec![0; size as usize];
+        if let Some(first) = bytes.first_mut() {
+            *first = if inhibit { on } else { 0 };
+        }
+        bytes
+    };
+
+    if let Ok(info) = smc.key_info("CHTE") {
+        return smc.write_bytes("CHTE", &value(info.data_size, 1));
+    }
+
+    let mut found = false;
+    for key in INHIBIT_KEYS {
+        if let Ok(info) = smc.key_info(key) {
+            smc.write_bytes(key, &value(info.data_size, INHIBIT))?;
+            found = true;
+        }
+    }
+    if found {
+        Ok(())
+    } else {
+        Err(SMCError::KeyNotFound(INHIBIT_KEYS[0].to_string()))
+    }
+}
+
+/// Whether a [`ChargeInhibitor::open`] left charging inhibited, undone
+/// through a fresh SMC connection when the process exits.
+#[cfg(apple_ffi)]
+mod exit_guard {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Once,
+    };
+
+    use super::{set_inhibit, SMCConnection};
+
+    static INHIBITED: AtomicBool = AtomicBool::new(false);
+    static REGISTER: Once = Once::new();
+
+    pub(super) fn track(inhibited: bool) {
+        REGISTER.call_once(|| unsafe {
+            libc::atexit(restore_at_exit);
+        });
+        INHIBITED.store(inhibited, Ordering::SeqCst);
+    }
+
+    extern "C" fn restore_at_exit() {
+        if !INHIBITED.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        if let Ok(mut smc) = SMCConnection::new("AppleSMC") {
+            let _ = set_inhibit(&mut smc, false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ffi::smc::{memory::SharedSMC, MemorySMC};
+
+    fn legacy_smc() -> SharedSMC {
+        SharedSMC::new(
+            MemorySMC::new()
+                .with_key("CH0B", "hex_", &[0])
+                .unwrap()
+                .with_key("CH0C", "hex_", &[0])
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn inhibits_and_restores_through_chte() {
+        let smc = SharedSMC::new(MemorySMC::new().with_key("CHTE", "ui32", &[0; 4]).unwrap());
+        let mut inhibitor = ChargeInhibitor::new(SMCConnection::with_transport(smc.clone()));
+
+        inhibitor.apply(ChargeState::Inhibited).unwrap();
+        assert!(inhibitor.is_inhibited());
+        assert_eq!(smc.borrow().bytes("CHTE"), Some(&[1u8, 0, 0, 0][..]));
+
+        inhibitor.restore().unwrap();
+        assert!(!inhibitor.is_inhibited());
+        assert_eq!(smc.borrow().bytes("CHTE"), Some(&[0u8; 4][..]));
+    }
+
+    #[test]
+    fn inhibits_through_legacy_keys_and_restores_on_drop() {
+        let smc = legacy_smc();
+        let mut inhibitor = ChargeInhibitor::new(SMCConnection::with_transport(smc.clone()));
+
+        inhibitor.apply(ChargeState::Inhibited).unwrap();
+        assert_eq!(smc.borrow().bytes("CH0B"), Some(&[INHIBIT][..]));
+        assert_eq!(smc.borrow().bytes("CH0C"), Some(&[INHIBIT][..]));
+
+        drop(inhibitor);
+        assert_eq!(smc.borrow().bytes("CH0B"), Some(&[0u8][..]));
+        assert_eq!(smc.borrow().bytes("CH0C"), Some(&[0u8][..]));
+    }
+
+    #[test]
+    fn a_failed_inhibit_is_still_undone() {
+        let smc = legacy_smc();
+        let mut inhibitor = ChargeInhibitor::new(SMCConnection::with_transport(smc.clone()));
+        // cache the info of both keys, then let the second write fail
+        inhibitor.restore().unwrap();
+        smc.borrow_mut().remove("CH0C");
+
+        assert!(inhibitor.apply(ChargeState::Inhibited).is_err());
+        assert!(inhibitor.is_inhibited());
+        assert_eq!(smc.borrow().bytes("CH0B"), Some(&[INHIBIT][..]));
+
+        drop(inhibitor);
+        assert_eq!(smc.borrow().bytes("CH0B"), Some(&[0u8][..]));
+    }
+
+    #[test]
+    fn clears_a_stale_inhibit() {
+        let smc = legacy_smc();
+        smc.borrow_mut().insert("CH0B", "hex_", &[INHIBIT]).unwrap();
+        let mut inhibitor = ChargeInhibitor::new(SMCConnection::with_transport(smc.clone()));
+        assert!(!inhibitor.is_inhibited());
+
+        inhibitor.restore().unwrap();
+        assert_eq!(smc.borrow().bytes("CH0B"), Some(&[0u8][..]));
+    }
+
+    #[test]
+    fn fails_without_inhibit_keys() {
+        let smc = MemorySMC::new().with_key("FNum", "ui8", &[0]).unwrap();
+        let mut inhibitor = ChargeInhibitor::new(SMCConnection::with_transport(smc));
+        assert!(matches!(
+            inhibitor.apply(ChargeState::Inhibited),
+            Err(SMCError::KeyNotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,361 @@
+//! Manual fan control, handed back to the SMC once we are done.
+//!
+//! Apple Silicon and later Intel machines switch a fan to manual with
+//! `F<n>Md`, the former also want `Ftst` set before the SMC accepts it.
+//! Older Intel machines use the `FS! ` bitmask instead.
+//!
+//! Fans left in manual mode are restored on exit, including when the
+//! process is terminated by a signal.
+
+use std::collections::BTreeSet;
+
+use thiserror::Error;
+
+use super::{FanData, SMCConnection, SMCError, SMCTransport};
+
+#[derive(Debug, Error)]
+pub enum FanControlError {
+    #[error("no fan {0}")]
+    NoSuchFan(u8),
+
+    #[error("the limits of fan {0} can't be read")]
+    UnknownLimits(u8),
+
+    #[error("{rpm} RPM is out of range for fan {fan} ({min}..={max})")]
+    OutOfRange {
+        fan: u8,
+        rpm: f32,
+        min: f32,
+        max: f32,
+    },
+
+    #[error(transparent)]
+    Smc(#[from] SMCError),
+}
+
+/// Drives the fans of an SMC, every fan switched to manual is returned to
+/// automatic control when this is dropped.
+pub struct FanControl<T: SMCTransport> {
+    smc: SMCConnection<T>,
+    manual: BTreeSet<u8>,
+    #[cfg(apple_ffi)]
+    restore_at_exit: bool,
+}
+
+#[cfg(apple_ffi)]
+impl FanControl<super::IOKitTransport> {
+    /// Control the fans of this Mac.
+    ///
+    /// Fans are also restored when the process exits without dropping this,
+    /// e.g. through [`std::process::exit`].
+    pub fn open() -> Result<Self, FanControlError> {
+        let mut control = Self::new(SMCConnection::new("AppleSMC")?);
+        control.restore_at_exit = true;
+        Ok(control)
+    }
+}
+
+impl<T: SMCTransport> FanControl<T> {
+    pub fn new(smc: SMCConnection<T>) -> Self {
+        Self {
+            smc,
+            manual: BTreeSet::new(),
+            #[cfg(apple_ffi)]
+            restore_at_exit: false,
+        }
+    }
+
+    pub fn fans(&mut self) -> Vec<FanData> {
+        (0..self.fan_count())
+            .map_while(|fan| self.smc.read_fan(fan))
+            .collect()
+    }
+
+    pub fn smc(&self) -> &SMCConnection<T> {
+        &self.smc
+    }
+
+    /// Fans currently under manual control.
+    pub fn manual(&self) -> impl Iterator<Item = u8> + '_ {
+        self.manual.iter().copied()
+    }
+
+    /// Take `fan` off automatic control, it keeps its current target.
+    pub fn set_manual(&mut self, fan: u8) -> Result<(), FanControlError> {
+        self.fan(fan)?;
+        self.set_mode(fan, true)?;
+        self.manual.insert(fan);
+        #[cfg(apple_ffi)]
+        if self.restore_at_exit {
+            exit_guard::track(fan);
+        }
+        Ok(())
+    }
+
+    /// Switch `fan` to manual and spin it at `rpm`, which has to be within
+    /// the fan's own min and max.
+    pub fn set_target(&mut self, fan: u8, rpm: f32) -> Result<(), FanControlError> {
+        let FanData { min, max, .. } = self.fan(fan)?;
+        if max <= 0. || min > max {
+            return Err(FanControlError::UnknownLimits(fan));
+        }
+        if !(min..=max).contains(&rpm) {
+            return Err(FanControlError::OutOfRange { fan, rpm, min, max });
+        }
+
+        self.set_manual(fan)?;
+        self.smc.write_value(&format!("F{fan}Tg"), rpm)?;
+        Ok(())
+    }
+
+    /// Hand `fan` back to the SMC.
+    pub fn restore(&mut self, fan: u8) -> Result<(), FanControlError> {
+        self.set_mode(fan, false)?;
+        self.manual.remove(&fan);
+        #[cfg(apple_ffi)]
+        exit_guard::untrack(fan);
+        Ok(())
+    }
+
+    /// Hand every fan we took over back to the SMC. Every fan is tried,
+    /// the first error is returned.
+    pub fn restore_all(&mut self) -> Result<(), FanControlError> {
+        let mut result = Ok(());
+        for fan in self.manual.clone() {
+            if let Err(err) = self.restore(fan) {
+                log::warn!("Failed to restore fan {fan}: {err}");
+                result = result.and(Err(err));
+            }
+        }
+        result
+    }
+
+    fn fan_count(&mut self) -> u8 {
+        self.smc.fan_count().min(10)
+    }
+
+    fn fan(&mut self, fan: u8) -> Result<FanData, FanControlError> {
+        if fan >= self.fan_count() {
+            return Err(FanControlError::NoSuchFan(fan));
+        }
+        self.smc
+            .read_fan(fan)
+            .ok_or(FanControlError::NoSuchFan(fan))
+    }
+
+    fn set_mode(&mut self, fan: u8, manual: bool) -> Result<(), FanControlError> {
+        set_mode(&mut self.smc, fan, manual, &self.manual)
+    }
+}
+
+impl<T: SMCTransport> Drop for FanControl<T> {
+    fn drop(&mut self) {
+        if let Err(err) = self.restore_all() {
+            log::error!("Failed to restore automatic fan control: {err}");
+        }
+    }
+}
+
+/// Switch `fan` between manual and automatic, `manual_fans` being the fans
+/// that are manual so far.
+fn set_mode<T: SMCTransport>(
+    smc: &mut SMCConnection<T>,
+    fan: u8,
+    manual: bool,
+    manual_fans: &BTreeSet<u8>,
+) -> Result<(), FanControlError> {
+    let mode_key = format!("F{fan}Md");
+    if smc.key_info(&mode_key).is_ok() {
+        let others = manual_fans.iter().any(|&f| f != fan);
+        if manual && smc.key_info("Ftst").is_ok() {
+            smc.write_value("Ftst", 1.)?;
+        }
+        smc.write_value(&mode_key, if manual { 1. } else { 0. })?;
+        if !manual && !others && smc.key_info("Ftst").is_ok() {
+            smc.write_value("Ftst", 0.)?;
+        }
+        return Ok(());
+    }
+
+    let mask = smc.read_key("FS! ")?.value()? as u16;
+    let mask = if manual {
+        mask | 1 << fan
+    } else {
+        mask & !(1 << fan)
+    };
+    smc.write_value("FS! ", mask as f32)?;
+    Ok(())
+}
+
+/// Fans taken over by a [`FanControl::open`], restored through a fresh SMC
+/// connection if the process exits while they are still manual.
+#[cfg(apple_ffi)]
+mod exit_guard {
+    use std::{
+        collections::BTreeSet,
+        sync::{Mutex, Once},
+        thread,
+    };
+
+    use signal_hook::{
+        consts::TERM_SIGNALS, iterator::Signals, low_level::emulate_default_handler,
+    };
+
+    use super::{set_mode, SMCConnection};
+
+    static MANUAL: Mutex<BTreeSet<u8>> = Mutex::new(BTreeSet::new());
+    static REGISTER: Once = Once::new();
+
+    pub(super) fn track(fan: u8) {
+        REGISTER.call_once(|| {
+            unsafe {
+                libc::atexit(restore_at_exit);
+            }
+            restore_on_signal();
+        });
+        MANUAL.lock().unwrap().insert(fan);
+    }
+
+    /// `atexit` handlers don't run when a signal terminates the process, so
+    /// restore from a thread waiting for them and then terminate anyway.
+    fn restore_on_signal() {
+        let Ok(mut signals) = Signals::new(TERM_SIGNALS) else {
+            log::warn!("Failed to watch for termination signals");
+            return;
+        };
+        thread::spawn(move || {
+            if let Some(signal) = signals.forever().next() {
+                restore_at_exit();
+                let _ = emulate_default_handler(signal);
+            }
+        });
+    }
+
+    pub(super) fn untrack(fan: u8) {
+        MANUAL.lock().unwrap().remove(&fan);
+    }
+
+    extern "C" fn restore_at_exit() {
+        let Ok(mut manual) = MANUAL.lock() else {
+            return;
+        };
+        if manual.is_empty() {
+            return;
+        }
+        let Ok(mut smc) = SMCConnection::new("AppleSMC") else {
+            return;
+        };
+        while let Some(fan) = manual.pop_first() {
+            let _ = set_mode(&mut smc, fan, false, &manual);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ffi::smc::{memory::SharedSMC, MemorySMC, SMCEndian};
+
+    fn smc(mode_keys: bool) -> MemorySMC {
+        let mut smc = MemorySMC::new();
+        smc.insert("FNum", "ui8", &[2]).unwrap();
+        for fan in 0..2 {
+            for (suffix, rpm) in [("Ac", 1200f32), ("Mn", 1000.), ("Mx", 6000.), ("Tg", 1200.)] {
+                smc.insert(&format!("F{fan}{suffix}"), "flt", &rpm.to_le_bytes())
+                    .unwrap();
+            }
+            if mode_keys {
+                smc.insert(&format!("F{fan}Md"), "ui8", &[0]).unwrap();
+            }
+        }
+        if mode_keys {
+            smc.insert("Ftst", "ui8", &[0]).unwrap();
+        }
+        smc
+    }
+
+    fn control(smc: MemorySMC) -> FanControl<MemorySMC> {
+        FanControl::new(SMCConnection::with_transport(smc))
+    }
+
+    fn bytes<'a>(control: &'a FanControl<MemorySMC>, key: &str) -> &'a [u8] {
+        control.smc().transport().bytes(key).unwrap()
+    }
+
+    #[test]
+    fn switches_fans_to_manual_and_back() {
+        let mut control = control(smc(true));
+        assert_eq!(control.fans().len(), 2);
+
+        control.set_target(0, 2000.).unwrap();
+        control.set_manual(1).unwrap();
+        assert_eq!(control.manual().collect::<Vec<_>>(), [0, 1]);
+        assert_eq!(bytes(&control, "F0Md"), [1]);
+        assert_eq!(bytes(&control, "F1Md"), [1]);
+        assert_eq!(bytes(&control, "Ftst"), [1]);
+        assert_eq!(bytes(&control, "F0Tg"), 2000f32.to_le_bytes());
+
+        // Ftst stays set while another fan is still manual
+        control.restore(0).unwrap();
+        assert_eq!(bytes(&control, "F0Md"), [0]);
+        assert_eq!(bytes(&control, "Ftst"), [1]);
+
+        control.restore_all().unwrap();
+        assert_eq!(bytes(&control, "F1Md"), [0]);
+        assert_eq!(bytes(&control, "Ftst"), [0]);
+        assert_eq!(control.manual().count(), 0);
+    }
+
+    #[test]
+    fn refuses_targets_out_of_range() {
+        let mut control = control(smc(true));
+        assert!(matches!(
+            control.set_target(0, 9000.),
+            Err(FanControlError::OutOfRange { fan: 0, .. })
+        ));
+        assert!(matches!(
+            control.set_target(2, 2000.),
+            Err(FanControlError::NoSuchFan(2))
+        ));
+        assert_eq!(control.manual().count(), 0);
+        assert_eq!(bytes(&control, "F0Md"), [0]);
+    }
+
+    #[test]
+    fn restores_every_fan_when_one_fails() {
+        let mut control = control(smc(true));
+        control.set_manual(0).unwrap();
+        control.set_manual(1).unwrap();
+        control.smc.transport.remove("F0Md");
+
+        assert!(control.restore_all().is_err());
+        assert_eq!(bytes(&control, "F1Md"), [0]);
+        assert_eq!(control.manual().collect::<Vec<_>>(), [0]);
+    }
+
+    #[test]
+    fn restores_on_drop() {
+        let smc = SharedSMC::new(smc(true));
+        let mut control = FanControl::new(SMCConnection::with_transport(smc.clone()));
+        control.set_manual(1).unwrap();
+        assert_eq!(smc.borrow().bytes("F1Md"), Some(&[1u8][..]));
+
+        drop(control);
+        assert_eq!(smc.borrow().bytes("F1Md"), Some(&[0u8][..]));
+        assert_eq!(smc.borrow().bytes("Ftst"), Some(&[0u8][..]));
+    }
+
+    #[test]
+    fn sets_the_intel_bitmask_big_endian() {
+        let mut smc = smc(false).with_endian(SMCEndian::Big);
+        smc.insert("FS! ", "ui16", &[0, 0]).unwrap();
+        let mut control = control(smc);
+
+        control.set_manual(1).unwrap();
+        assert_eq!(bytes(&control, "FS! "), [0x00, 0x02]);
+        control.set_manual(0).unwrap();
+        assert_eq!(bytes(&control, "FS! "), [0x00, 0x03]);
+        control.restore_all().unwrap();
+        assert_eq!(bytes(&control, "FS! "), [0x00, 0x00]);
+    }
+}
@@ -0,0 +1,271 @@
+//! An SMC living in memory, to run the SMC logic without the hardware.
+
+use indexmap::IndexMap;
+
+use super::{
+    kern_return_t, key_to_u32, str_to_u32, KeyInfo, SMCEndian, SMCError, SMCKeyData, SMCTransport,
+    CMD_READ_BYTES, CMD_READ_INDEX, CMD_READ_KEYINFO, CMD_WRITE_BYTES, KERN_INVALID_ARGUMENT,
+    SMC_KEY_NOT_FOUND,
+};
+
+#[derive(Debug, Clone, Copy)]
+struct MemoryKey {
+    info: KeyInfo,
+    bytes: [u8; 32],
+}
+
+/// Answers the SMC struct call from a key table.
+///
+/// `#KEY` is derived from the table unless it is stored in it.
+#[derive(Debug, Clone, Default)]
+pub struct MemorySMC {
+    keys: IndexMap<u32, MemoryKey>,
+    endian: SMCEndian,
+}
+
+impl MemorySMC {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer like an SMC with integers in `endian`, e.g. an Intel one.
+    #[must_use]
+    pub fn with_endian(mut self, endian: SMCEndian) -> Self {
+        self.endian = endian;
+        self
+    }
+
+    pub fn with_key(mut self, key: &str, data_type: &str, bytes: &[u8]) -> Result<Self, SMCError> {
+        self.insert(key, data_type, bytes)?;
+        Ok(self)
+    }
+
+    /// Add or replace `key`, `bytes` being the value already encoded as
+    /// `data_type`.
+    pub fn insert(&mut self, key: &str, data_type: &str, bytes: &[u8]) -> Result<(), SMCError> {
+        let key_int = key_to_u32(key)?;
+        if data_type.len() > 4 || !data_type.is_ascii() {
+            return Err(SMCError::InvalidType(data_type.to_string()));
+        }
+        let mut ty = [b' '; 4];
+        ty[..data_type.len()].copy_from_slice(data_type.as_bytes());
+
+        let mut buf = [0u8; 32];
+        if bytes.len() > buf.len() {
+            return Err(SMCError::BadSize {
+                key: key.to_string(),
+                expected: buf.len() as u32,
+                actual: bytes.len() as u32,
+            });
+        }
+        buf[..bytes.len()].copy_from_slice(bytes);
+
+        self.keys.insert(key_int, MemoryKey {
+            info: KeyInfo {
+                data_size: bytes.len() as u32,
+                data_type: u32::from_be_bytes(ty),
+                data_attributes: 0,
+            },
+            bytes: buf,
+        });
+        Ok(())
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        if let Ok(key) = key_to_u32(key) {
+            self.keys.shift_remove(&key);
+        }
+    }
+
+    /// Current value of `key`, e.g. to check what was written to it.
+    pub fn bytes(&self, key: &str) -> Option<&[u8]> {
+        self.keys
+            .get(&key_to_u32(key).ok()?)
+            .map(|k| &k.bytes[..k.info.data_size as usize])
+    }
+}
+
+impl SMCTransport for MemorySMC {
+    fn call(&mut self, input: &SMCKeyData) -> Result<SMCKeyData, kern_return_t> {
+        let mut output = SMCKeyData::default();
+
+        if input.key == str_to_u32("#KEY")
+            && input.data8 != CMD_READ_INDEX
+            && !self.keys.contains_key(&input.key)
+        {
+            output.key_info = KeyInfo {
+                data_size: 4,
+                data_type: str_to_u32("ui32"),
+                data_attributes: 0,
+            };
+            // big-endian whatever the other integers are
+            output.bytes[..4].copy_from_slice(&(self.keys.len() as u32).to_be_bytes());
+            return Ok(output);
+        }
+
+        if input.data8 == CMD_READ_INDEX {
+            match self.keys.get_index(input.data32 as usize) {
+                Some((key, _)) => output.key = *key,
+                None => output.result = SMC_KEY_NOT_FOUND,
+            }
+            return Ok(output);
+        }
+
+        let Some(entry) = self.keys.get_mut(&input.key) else {
+            output.result = SMC_KEY_NOT_FOUND;
+            return Ok(output);
+        };
+        match input.data8 {
+            CMD_READ_KEYINFO => output.key_info = entry.info,
+            CMD_READ_BYTES => output.bytes = entry.bytes,
+            CMD_WRITE_BYTES => {
+                if input.key_info.data_size != entry.info.data_size {
+                    return Err(KERN_INVALID_ARGUMENT);
+                }
+                entry.bytes = input.bytes;
+            }
+            _ => return Err(KERN_INVALID_ARGUMENT),
+        }
+        Ok(output)
+    }
+
+    fn endian(&self) -> SMCEndian {
+        self.endian
+    }
+}
+
+/// A [`MemorySMC`] that stays reachable after whatever owns its connection
+/// is dropped.
+#[cfg(test)]
+#[derive(Clone)]
+pub(crate) struct SharedSMC(std::rc::Rc<std::cell::RefCell<MemorySMC>>);
+
+#[cfg(test)]
+impl SharedSMC {
+    pub fn new(smc: MemorySMC) -> Self {
+        Self(std::rc::Rc::new(std::cell::RefCell::new(smc)))
+    }
+
+    pub fn borrow(&self) -> std::cell::Ref<'_, MemorySMC> {
+        self.0.borrow()
+    }
+
+    pub fn borrow_mut(&self) -> std::cell::RefMut<'_, MemorySMC> {
+        self.0.borrow_mut()
+    }
+}
+
+#[cfg(test)]
+impl SMCTransport for SharedSMC {
+    fn call(&mut self, input: &SMCKeyData) -> Result<SMCKeyData, kern_return_t> {
+        self.0.borrow_mut().call(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indexmap::IndexMap;
+
+    use super::*;
+    use crate::ffi::smc::{
+        PowerField, SMCConnection, SMCReadSensor, SMCReadThermal, SensorProfile, ThermalField,
+    };
+
+    fn smc() -> SMCConnection<MemorySMC> {
+        let smc = MemorySMC::new()
+            .with_key("PSTR", "flt ", &12.5f32.to_le_bytes())
+            .unwrap()
+            .with_key("PDTR", "flt ", &30f32.to_le_bytes())
+            .unwrap()
+            .with_key("TC0P", "sp78", &[0x2d, 0x80])
+            .unwrap()
+            .with_key("TC1P", "sp78", &[0x2e, 0x80])
+            .unwrap()
+            .with_key("FNum", "ui8", &[0])
+            .unwrap()
+            .with_key("CHTE", "ui32", &[0; 4])
+            .unwrap();
+        SMCConnection::with_transport(smc)
+    }
+
+    fn keys(keys: &[&str]) -> Vec<String> {
+        keys.iter().map(|k| k.to_string()).collect()
+    }
+
+    #[test]
+    fn reads_sensors_through_a_profile() {
+        let profile = SensorProfile {
+            sensors: IndexMap::from([
+                (PowerField::SystemTotal, keys(&["PSTR"])),
+                // the first readable key of the chain wins
+                (PowerField::DeliveryRate, keys(&["PDTX", "PDTR"])),
+                (PowerField::Brightness, keys(&["PDBR"])),
+            ]),
+            thermal: IndexMap::from([(ThermalField::Cpu, keys(&["TC0P", "TC1P", "TC2P"]))]),
+            ..Default::default()
+        };
+        let mut smc = smc();
+
+        let power = smc.read_sensor(&profile);
+        assert_eq!(power.system_total, 12.5);
+        assert_eq!(power.delivery_rate, 30.);
+        assert_eq!(power.brightness, 0.);
+
+        let thermal = smc.read_thermal(&profile);
+        assert_eq!(thermal.cpu, Some(46.));
+        assert_eq!(thermal.gpu, None);
+        assert!(thermal.fans.is_empty());
+    }
+
+    #[test]
+    fn reports_missing_keys() {
+        let mut smc = smc();
+        assert!(matches!(smc.read_key("PPBR"), Err(SMCError::KeyNotFound(k)) if k == "PPBR"));
+        assert!(matches!(smc.read_key("PPB"), Err(SMCError::InvalidKey(_))));
+        assert!(matches!(
+            smc.write_value("PPBR", 1.),
+            Err(SMCError::KeyNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn validates_writes() {
+        let mut smc = smc();
+        assert!(matches!(
+            smc.write_bytes("CHTE", &[1, 0]),
+            Err(SMCError::BadSize {
+                expected: 4,
+                actual: 2,
+                ..
+            })
+        ));
+        assert!(matches!(
+            smc.write_value("CHTE", -1.),
+            Err(SMCError::Decode { .. })
+        ));
+        assert_eq!(smc.transport().bytes("CHTE"), Some(&[0u8; 4][..]));
+
+        smc.write_value("CHTE", 1.).unwrap();
+        assert_eq!(smc.transport().bytes("CHTE"), Some(&[1u8, 0, 0, 0][..]));
+        assert_eq!(smc.read_key("CHTE").unwrap().value().unwrap(), 1.);
+    }
+
+    #[test]
+    fn validates_inserted_keys() {
+        let mut smc = MemorySMC::new();
+        assert!(matches!(
+            smc.insert("ABC", "ui8", &[0]),
+            Err(SMCError::InvalidKey(_))
+        ));
+        assert!(matches!(
+            smc.insert("ABCD", "ui16x", &[0, 0]),
+            Err(SMCError::InvalidType(_))
+        ));
+        assert!(matches!(
+            smc.insert("ABCD", "ch8*", &[0; 33]),
+            Err(SMCError::BadSize { actual: 33, .. })
+        ));
+        assert_eq!(smc.bytes("AB"), None);
+        smc.remove("AB");
+    }
+}
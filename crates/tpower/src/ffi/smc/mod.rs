@@ -0,0 +1,1017 @@
+#[cfg(apple_ffi)]
+use core::mem::size_of;
+use core::str;
+#[cfg(apple_ffi)]
+use std::ffi::CString;
+use std::{collections::HashMap, fmt, str::FromStr};
+
+#[cfg(apple_ffi)]
+use io_kit_sys::{
+    types::{io_connect_t, io_service_t},
+    IOConnectCallStructMethod, IOIteratorNext, IOMasterPort, IOObjectRelease, IOServiceClose,
+    IOServiceGetMatchingServices, IOServiceMatching, IOServiceOpen,
+};
+#[cfg(apple_ffi)]
+use mach::{kern_return::KERN_SUCCESS, port::mach_port_t, traps::mach_task_self};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+mod charge;
+mod fan;
+mod memory;
+mod profile;
+mod thermal;
+
+pub use charge::ChargeInhibitor;
+pub use fan::{FanControl, FanControlError};
+pub use memory::MemorySMC;
+pub use profile::{PowerField, ProfileError, SensorProfile, SensorProfiles, ThermalField};
+pub use thermal::{FanData, SMCReadThermal, ThermalData};
+
+/// `kern_return_t`, spelled out so the SMC logic builds without the mach crate
+#[allow(non_camel_case_types)]
+pub type kern_return_t = i32;
+
+const KERN_INVALID_ARGUMENT: kern_return_t = 4;
+/// `kIOReturnNotPrivileged`
+const IO_RETURN_NOT_PRIVILEGED: kern_return_t = 0xe00002c1_u32 as kern_return_t;
+
+// Kernel values
+#[cfg(apple_ffi)]
+const KERNEL_INDEX_SMC: i32 = 2;
+
+// SMC CMD values
+const CMD_READ_BYTES: u8 = 5;
+const CMD_WRITE_BYTES: u8 = 6;
+const CMD_READ_INDEX: u8 = 8;
+const CMD_READ_KEYINFO: u8 = 9;
+
+// SMC result values
+const SMC_KEY_NOT_FOUND: u8 = 0x84;
+
+/// Number of keys the SMC knows about.
+const KEY_COUNT: &str = "#KEY";
+/// Listing keys stops after this many unreadable indices in a row.
+const MAX_UNREADABLE_KEYS: u32 = 64;
+
+#[derive(Debug, Error)]
+pub enum SMCError {
+    #[error("IOKit service {0:?} not found")]
+    ServiceNotFound(String),
+
+    #[error("SMC key {0:?} not found")]
+    KeyNotFound(String),
+
+    #[error("invalid SMC key {0:?}, keys are four ASCII characters")]
+    InvalidKey(String),
+
+    #[error("invalid SMC data type {0:?}, types are up to four ASCII characters")]
+    InvalidType(String),
+
+    #[error("SMC key {key:?} holds {expected} bytes, got {actual}")]
+    BadSize {
+        key: String,
+        expected: u32,
+        actual: u32,
+    },
+
+    #[error("not privileged to access the SMC, try running as root")]
+    Privilege,
+
+    #[error("SMC key {key:?} of type {data_type:?} can't be converted")]
+    Decode { key: String, data_type: String },
+
+    #[error("SMC call failed: {0:#x}")]
+    Call(kern_return_t),
+}
+
+impl From<kern_return_t> for SMCError {
+    fn from(value: kern_return_t) -> Self {
+        match value {
+            IO_RETURN_NOT_PRIVILEGED => SMCError::Privilege,
+            value => SMCError::Call(value),
+        }
+    }
+}
+
+pub trait SMCReadSensor {
+    fn read_sensor(&mut self, profile: &SensorProfile) -> SMCPowerData;
+}
+
+impl<T: SMCTransport> SMCReadSensor for SMCConnection<T> {
+    fn read_sensor(&mut self, profile: &SensorProfile) -> SMCPowerData {
+        profile
+            .sensors
+            .iter()
+            .fold(SMCPowerData::default(), |mut acc, (field, keys)| {
+                // the first key of the chain that can be read wins
+                if let Some(val) = keys
+                    .iter()
+                    .find_map(|key| self.read_key(key).and_then(|val| val.value()).ok())
+                {
+                    *field.get_mut(&mut acc) = val;
+                }
+                acc
+            })
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[serde(rename_all = "camelCase")]
+pub struct SMCPowerData {
+    pub battery_rate: f32,
+    pub delivery_rate: f32,
+    pub system_total: f32,
+    pub heatpipe: f32,
+    pub brightness: f32,
+    pub full_charge_capacity: f32,
+    pub current_capacity: f32,
+    pub charging_status: f32,
+    pub time_to_empty: f32,
+    pub time_to_full: f32,
+    pub temperature: f32,
+}
+
+impl SMCPowerData {
+    pub fn is_charging(&self) -> bool {
+        self.charging_status > f32::EPSILON
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct DataVers {
+    pub major: u8,
+    pub minor: u8,
+    pub build: u8,
+    pub reserved: [u8; 1],
+    pub release: u16,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct PLimitData {
+    pub version: u16,
+    pub length: u16,
+    pub cpu_plimit: u32,
+    pub gpu_plimit: u32,
+    pub mem_plimit: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct KeyInfo {
+    pub data_size: u32,
+    pub data_type: u32,
+    pub data_attributes: u8,
+}
+
+/// A key as listed by [`SMCConnection::keys`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[serde(rename_all = "camelCase")]
+pub struct SMCKeyEntry {
+    pub key: String,
+    pub data_size: u32,
+    pub data_type: String,
+    pub data_attributes: u8,
+    /// `None` if the key could not be read, e.g. it is write-only
+    pub value: Option<SMCValue>,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SMCKeyData {
+    pub key: u32,
+    pub vers: DataVers,
+    pub plimit_data: PLimitData,
+    pub key_info: KeyInfo,
+    pub result: u8,
+    pub status: u8,
+    pub data8: u8,
+    pub data32: u32,
+    pub bytes: [u8; 32],
+}
+
+/// A decoded SMC value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[serde(tag = "type", content = "value", rename_all = "camelCase")]
+pub enum SMCValue {
+    Int(i64),
+    Float(f32),
+    Bool(bool),
+    Str(String),
+    Bytes(Vec<u8>),
+}
+
+impl SMCValue {
+    pub fn as_f32(&self) -> Option<f32> {
+        match self {
+            SMCValue::Int(v) => Some(*v as f32),
+            SMCValue::Float(v) => Some(*v),
+            SMCValue::Bool(v) => Some(u8::from(*v).into()),
+            SMCValue::Str(_) | SMCValue::Bytes(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for SMCValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SMCValue::Int(v) => write!(f, "{v}"),
+            SMCValue::Float(v) => write!(f, "{v}"),
+            SMCValue::Bool(v) => write!(f, "{v}"),
+            SMCValue::Str(v) => write!(f, "{v:?}"),
+            SMCValue::Bytes(v) => v.iter().try_for_each(|b| write!(f, "{b:02x}")),
+        }
+    }
+}
+
+/// Byte order of the integer types, which differs between SMCs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SMCEndian {
+    /// Apple Silicon
+    #[default]
+    Little,
+    /// Intel
+    Big,
+}
+
+impl SMCEndian {
+    /// The SMC of the machine we run on.
+    pub const fn native() -> Self {
+        if cfg!(target_arch = "x86_64") {
+            SMCEndian::Big
+        } else {
+            SMCEndian::Little
+        }
+    }
+
+    /// Put `bytes`, given in little-endian, in this order.
+    fn order(self, mut bytes: Vec<u8>) -> Vec<u8> {
+        if self == SMCEndian::Big {
+            bytes.reverse();
+        }
+        bytes
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SMCType {
+    /// `ui8`, `ui16`, `ui32` and `ui64`
+    UInt,
+    /// `si8`, `si16`, `si32` and `si64`
+    SInt,
+    /// `fpXY` and `spXY`, `Y` being the number of fraction bits in hex,
+    /// e.g. `fpe2` has 14 integer and 2 fraction bits
+    Fixed {
+        signed: bool,
+        fraction_bits: u8,
+    },
+    FLT,
+    /// 48.16 fixed point
+    IOFT,
+    FLAG,
+    /// `ch8*`
+    CH8,
+    /// `{fds`, a fan descriptor struct
+    FDS,
+    /// `_hex`
+    HEX,
+}
+
+impl FromStr for SMCType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ui8" | "ui16" | "ui32" | "ui64" => Ok(SMCType::UInt),
+            "si8" | "si16" | "si32" | "si64" => Ok(SMCType::SInt),
+            "flt" => Ok(SMCType::FLT),
+            "ioft" => Ok(SMCType::IOFT),
+            "flag" => Ok(SMCType::FLAG),
+            "ch8*" => Ok(SMCType::CH8),
+            "{fds" => Ok(SMCType::FDS),
+            "_hex" => Ok(SMCType::HEX),
+            s if s.len() == 4 && (s.starts_with("fp") || s.starts_with("sp")) => {
+                let fraction_bits =
+                    u8::from_str_radix(s.get(3..).ok_or(())?, 16).map_err(|_| ())?;
+                Ok(SMCType::Fixed {
+                    signed: s.starts_with("sp"),
+                    fraction_bits,
+                })
+            }
+            _ => Err(()),
+        }
+    }
+}
+
+impl SMCType {
+    /// Decode the first `data_size` bytes of a key.
+    ///
+    /// Integers come in the byte order of the SMC, `flt` and `ioft` only
+    /// exist on Apple Silicon and are little-endian, the fixed point types
+    /// are left over from the Intel SMC and stay big-endian.
+    pub fn decode(self, bytes: &[u8], endian: SMCEndian) -> Option<SMCValue> {
+        let value = match self {
+            SMCType::UInt | SMCType::SInt => {
+                if !matches!(bytes.len(), 1 | 2 | 4 | 8) {
+                    return None;
+                }
+                let mut le = endian.order(bytes.to_vec());
+                let negative = self == SMCType::SInt && le.last()? & 0x80 != 0;
+                le.resize(8, if negative { 0xff } else { 0 });
+                let raw = u64::from_le_bytes(le.try_into().ok()?);
+                SMCValue::Int(if self == SMCType::UInt && raw > i64::MAX as u64 {
+                    return None;
+                } else {
+                    raw as i64
+                })
+            }
+            SMCType::Fixed {
+                signed,
+                fraction_bits,
+            } => {
+                let raw = match *bytes {
+                    [a, b] if signed => i16::from_be_bytes([a, b]) as f64,
+                    [a, b] => u16::from_be_bytes([a, b]) as f64,
+                    [a, b, c, d] if signed => i32::from_be_bytes([a, b, c, d]) as f64,
+                    [a, b, c, d] => u32::from_be_bytes([a, b, c, d]) as f64,
+                    _ => return None,
+                };
+                SMCValue::Float((raw / (1u64 << fraction_bits) as f64) as f32)
+            }
+            SMCType::FLT => SMCValue::Float(f32::from_le_bytes(bytes.try_into().ok()?)),
+            SMCType::IOFT => {
+                SMCValue::Float((u64::from_le_bytes(bytes.try_into().ok()?) as f64 / 65536.) as f32)
+            }
+            SMCType::FLAG => SMCValue::Bool(*bytes.first()? != 0),
+            SMCType::CH8 => {
+                let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+                SMCValue::Str(String::from_utf8_lossy(&bytes[..end]).into_owned())
+            }
+            SMCType::FDS | SMCType::HEX => SMCValue::Bytes(bytes.to_vec()),
+        };
+        Some(value)
+    }
+
+    /// Encode `value` into `size` bytes, the inverse of [`SMCType::decode`].
+    ///
+    /// Returns `None` for types that don't hold a number, and for values
+    /// that don't fit in `size` bytes of the type.
+    pub fn encode(self, value: f32, size: usize, endian: SMCEndian) -> Option<Vec<u8>> {
+        if !value.is_finite() {
+            return None;
+        }
+        let value = value as f64;
+        let bytes = match self {
+            SMCType::UInt | SMCType::SInt if matches!(size, 1 | 2 | 4 | 8) => {
+                let bits = size as u32 * 8;
+                let (min, max) = if self == SMCType::SInt {
+                    (
+                        -(2f64.powi(bits as i32 - 1)),
+                        2f64.powi(bits as i32 - 1) - 1.,
+                    )
+                } else {
+                    (0., 2f64.powi(bits as i32) - 1.)
+                };
+                let value = value.round();
+                if !(min..=max).contains(&value) {
+                    return None;
+                }
+                let raw = if value < 0. {
+                    value as i64 as u64
+                } else {
+                    value as u64
+                };
+                endian.order(raw.to_le_bytes()[..size].to_vec())
+            }
+            SMCType::Fixed {
+                signed,
+                fraction_bits,
+            } if matches!(size, 2 | 4) => {
+                let bits = size as i32 * 8;
+                let raw = (value * (1u64 << fraction_bits) as f64).round();
+                let (min, max) = if signed {
+                    (-(2f64.powi(bits - 1)), 2f64.powi(bits - 1) - 1.)
+                } else {
+                    (0., 2f64.powi(bits) - 1.)
+                };
+                if !(min..=max).contains(&raw) {
+                    return None;
+                }
+                (raw as i64).to_be_bytes()[8 - size..].to_vec()
+            }
+            SMCType::FLT if size == 4 => (value as f32).to_le_bytes().to_vec(),
+            SMCType::IOFT if size == 8 => {
+                let raw = (value * 65536.).round();
+                if !(0. ..=u64::MAX as f64).contains(&raw) {
+                    return None;
+                }
+                (raw as u64).to_le_bytes().to_vec()
+            }
+            SMCType::FLAG if size > 0 => {
+                let mut bytes = vec![0; size];
+                bytes[0] = u8::from(value != 0.);
+                bytes
+            }
+            _ => return None,
+        };
+        Some(bytes)
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SMCVal {
+    pub key: [u8; 4],
+    pub data_size: u32,
+    pub data_type: [u8; 4],
+    pub bytes: [u8; 32],
+    pub endian: SMCEndian,
+}
+
+impl SMCVal {
+    pub fn value(&self) -> Result<f32, SMCError> {
+        self.decode()?.as_f32().ok_or_else(|| self.decode_error())
+    }
+
+    /// Decode the value by its data type, types we don't know are returned
+    /// as raw bytes.
+    pub fn decode(&self) -> Result<SMCValue, SMCError> {
+        let bytes = &self.bytes[..(self.data_size as usize).min(self.bytes.len())];
+        let data_type = str::from_utf8(&self.data_type).map_err(|_| self.decode_error())?;
+        match SMCType::from_str(data_type.trim()) {
+            Ok(ty) => ty
+                .decode(bytes, self.endian)
+                .ok_or_else(|| self.decode_error()),
+            Err(_) => Ok(SMCValue::Bytes(bytes.to_vec())),
+        }
+    }
+
+    fn decode_error(&self) -> SMCError {
+        SMCError::Decode {
+            key: String::from_utf8_lossy(&self.key).into_owned(),
+            data_type: String::from_utf8_lossy(&self.data_type).trim().to_string(),
+        }
+    }
+}
+
+/// The struct call the SMC is driven through.
+pub trait SMCTransport {
+    fn call(&mut self, input: &SMCKeyData) -> Result<SMCKeyData, kern_return_t>;
+
+    /// Byte order of the integers behind this transport.
+    fn endian(&self) -> SMCEndian {
+        SMCEndian::Little
+    }
+}
+
+/// The real SMC, reached through IOKit.
+#[cfg(apple_ffi)]
+pub struct IOKitTransport {
+    conn: io_connect_t,
+}
+
+#[cfg(apple_ffi)]
+impl IOKitTransport {
+    pub fn open(service_name: &str) -> Result<Self, SMCError> {
+        let mut master_port: mach_port_t = 0;
+        let mut iterator = 0;
+        let device: io_service_t;
+        let mut conn: io_connect_t = 0;
+
+        unsafe {
+            // Get master port
+            let result = IOMasterPort(0, &mut master_port);
+            if result != KERN_SUCCESS {
+                return Err(result.into());
+            }
+
+            // Create matching dictionary
+            let service = CString::new(service_name)
+                .map_err(|_| SMCError::ServiceNotFound(service_name.to_string()))?;
+            let matching = IOServiceMatching(service.as_ptr());
+
+            // Get matching services
+            let result = IOServiceGetMatchingServices(master_port, matching, &mut iterator);
+            if result != KERN_SUCCESS {
+                return Err(result.into());
+            }
+
+            // Get first device
+            device = IOIteratorNext(iterator);
+            if device == 0 {
+                IOObjectRelease(iterator);
+                return Err(SMCError::ServiceNotFound(service_name.to_string()));
+            }
+
+            // Open connection
+            let result = IOServiceOpen(device, mach_task_self(), 0, &mut conn);
+
+            // Cleanup
+            IOObjectRelease(device);
+            IOObjectRelease(iterator);
+
+            if result != KERN_SUCCESS {
+                return Err(result.into());
+            }
+        }
+
+        Ok(IOKitTransport { conn })
+    }
+}
+
+#[cfg(apple_ffi)]
+impl SMCTransport for IOKitTransport {
+    fn call(&mut self, input: &SMCKeyData) -> Result<SMCKeyData, kern_return_t> {
+        let mut output = SMCKeyData::default();
+
+        unsafe {
+            let result = IOConnectCallStructMethod(
+                self.conn,
+                KERNEL_INDEX_SMC as u32,
+                input as *const _ as *const _,
+                size_of::<SMCKeyData>(),
+                &mut output as *mut _ as *mut _,
+                &mut size_of::<SMCKeyData>(),
+            );
+
+            if result != KERN_SUCCESS {
+                return Err(result);
+            }
+        }
+
+        Ok(output)
+    }
+
+    fn endian(&self) -> SMCEndian {
+        SMCEndian::native()
+    }
+}
+
+#[cfg(apple_ffi)]
+impl Drop for IOKitTransport {
+    fn drop(&mut self) {
+        unsafe {
+            IOServiceClose(self.conn);
+        }
+    }
+}
+
+pub struct SMCConnection<T> {
+    transport: T,
+    key_info_cache: HashMap<u32, KeyInfo>,
+}
+
+#[cfg(apple_ffi)]
+impl SMCConnection<IOKitTransport> {
+    pub fn new(service_name: &str) -> Result<Self, SMCError> {
+        Ok(Self::with_transport(IOKitTransport::open(service_name)?))
+    }
+}
+
+impl<T: SMCTransport> SMCConnection<T> {
+    pub fn with_transport(transport: T) -> Self {
+        SMCConnection {
+            transport,
+            key_info_cache: HashMap::with_capacity(100),
+        }
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    pub fn read_key(&mut self, key: &str) -> Result<SMCVal, SMCError> {
+        let key_int = key_to_u32(key)?;
+        let mut val = SMCVal::default();
+
+        // First get key info from cache or SMC
+        let key_info = self.get_key_info(key_int)?;
+
+        // Setup input structure
+        let input = SMCKeyData {
+            key: key_int,
+            data8: CMD_READ_BYTES,
+            key_info,
+            ..Default::default()
+        };
+
+        // Call SMC
+        let output = self.call(&input)?;
+
+        // Copy data to val
+        val.key.copy_from_slice(key.as_bytes());
+        val.data_size = key_info.data_size;
+        val.data_type
+            .copy_from_slice(&u32_to_bytes(key_info.data_type));
+        val.bytes = output.bytes;
+        // the key count is big-endian on every SMC
+        val.endian = if key == KEY_COUNT {
+            SMCEndian::Big
+        } else {
+            self.transport.endian()
+        };
+
+        Ok(val)
+    }
+
+    /// Number of keys the SMC knows about.
+    pub fn key_count(&mut self) -> Result<u32, SMCError> {
+        let val = self.read_key(KEY_COUNT)?;
+        match val.decode()? {
+            SMCValue::Int(count) => u32::try_from(count).map_err(|_| val.decode_error()),
+            _ => Err(val.decode_error()),
+        }
+    }
+
+    /// Name of the key at `index`, in `0..key_count()`.
+    pub fn key_at(&mut self, index: u32) -> Result<String, SMCError> {
+        let input = SMCKeyData {
+            data8: CMD_READ_INDEX,
+            data32: index,
+            ..Default::default()
+        };
+
+        let output = self.call(&input).map_err(|err| match err {
+            SMCError::KeyNotFound(_) => SMCError::KeyNotFound(format!("#{index}")),
+            err => err,
+        })?;
+        Ok(String::from_utf8_lossy(&u32_to_bytes(output.key)).into_owned())
+    }
+
+    pub fn key_info(&mut self, key: &str) -> Result<KeyInfo, SMCError> {
+        self.get_key_info(key_to_u32(key)?)
+    }
+
+    /// Describe `key` and read its current value.
+    pub fn describe_key(&mut self, key: &str) -> Result<SMCKeyEntry, SMCError> {
+        let info = self.key_info(key)?;
+        let data_type = u32_to_bytes(info.data_type);
+
+        Ok(SMCKeyEntry {
+            key: key.to_string(),
+            data_size: info.data_size,
+            data_type: String::from_utf8_lossy(&data_type).trim().to_string(),
+            data_attributes: info.data_attributes,
+            value: self.read_key(key).and_then(|val| val.decode()).ok(),
+        })
+    }
+
+    /// Every key the SMC knows about, with its info and current value.
+    pub fn keys(&mut self) -> Result<Vec<SMCKeyEntry>, SMCError> {
+        self.keys_matching("")
+    }
+
+    /// The keys starting with `prefix`. Keys whose name or info can't be
+    /// read are skipped, keys that can't be read have no value.
+    pub fn keys_matching(&mut self, prefix: &str) -> Result<Vec<SMCKeyEntry>, SMCError> {
+        let mut keys = vec![];
+        let mut unreadable = 0;
+        for index in 0..self.key_count()? {
+            match self.key_at(index) {
+                Ok(key) => {
+                    unreadable = 0;
+                    if key.starts_with(prefix) {
+                        keys.push(key);
+                    }
+                }
+                Err(_) => {
+                    unreadable += 1;
+                    // past the end of the table, the count can't be trusted
+                    if unreadable == MAX_UNREADABLE_KEYS {
+                        break;
+                    }
+                }
+            }
+        }
+        let entries = keys
+            .into_iter()
+            .filter_map(|key| self.describe_key(&key).ok())
+            .collect();
+        Ok(entries)
+    }
+
+    fn get_key_info(&mut self, key: u32) -> Result<KeyInfo, SMCError> {
+        // Try cache first
+        if let Some(info) = self.key_info_cache.get(&key) {
+            return Ok(*info);
+        }
+
+        // Not in cache, need to query SMC
+        let input = SMCKeyData {
+            key,
+            data8: CMD_READ_KEYINFO,
+            ..Default::default()
+        };
+
+        let output = self.call(&input)?;
+
+        // Cache the result
+        let info = output.key_info;
+        self.key_info_cache.insert(key, info);
+
+        Ok(info)
+    }
+
+    pub fn write_key(&mut self, val: &SMCVal) -> Result<(), SMCError> {
+        let key_name = String::from_utf8_lossy(&val.key).into_owned();
+        let key = key_to_u32(&key_name)?;
+
+        // Get key info first
+        let key_info = self.get_key_info(key)?;
+
+        // Verify data size matches
+        if key_info.data_size != val.data_size {
+            return Err(SMCError::BadSize {
+                key: key_name,
+                expected: key_info.data_size,
+                actual: val.data_size,
+            });
+        }
+
+        let input = SMCKeyData {
+            key,
+            data8: CMD_WRITE_BYTES,
+            bytes: val.bytes,
+            key_info,
+            ..Default::default()
+        };
+
+        self.call(&input)?;
+        Ok(())
+    }
+
+    /// Write a number to `key`, encoded as the key's own data type.
+    pub fn write_value(&mut self, key: &str, value: f32) -> Result<(), SMCError> {
+        let info = self.key_info(key)?;
+        let data_type = u32_to_bytes(info.data_type);
+        let bytes = str::from_utf8(&data_type)
+            .ok()
+            .and_then(|ty| SMCType::from_str(ty.trim()).ok())
+            .and_then(|ty| ty.encode(value, info.data_size as usize, self.transport.endian()))
+            .ok_or_else(|| SMCError::Decode {
+                key: key.to_string(),
+                data_type: String::from_utf8_lossy(&data_type).trim().to_string(),
+            })?;
+        self.write_bytes(key, &bytes)
+    }
+
+    /// Write raw `bytes` to `key`, they have to be exactly the key's size.
+    pub fn write_bytes(&mut self, key: &str, bytes: &[u8]) -> Result<(), SMCError> {
+        let info = self.key_info(key)?;
+        if bytes.len() != info.data_size as usize || bytes.len() > 32 {
+            return Err(SMCError::BadSize {
+                key: key.to_string(),
+                expected: info.data_size,
+                actual: bytes.len() as u32,
+            });
+        }
+
+        let mut val = SMCVal {
+            data_size: info.data_size,
+            data_type: u32_to_bytes(info.data_type),
+            ..Default::default()
+        };
+        val.key.copy_from_slice(key.as_bytes());
+        val.bytes[..bytes.len()].copy_from_slice(bytes);
+        self.write_key(&val)
+    }
+
+    fn call(&mut self, input: &SMCKeyData) -> Result<SMCKeyData, SMCError> {
+        let output = self.transport.call(input)?;
+        if output.result == SMC_KEY_NOT_FOUND {
+            return Err(SMCError::KeyNotFound(
+                String::from_utf8_lossy(&u32_to_bytes(input.key)).into_owned(),
+            ));
+        }
+        Ok(output)
+    }
+}
+
+fn key_to_u32(key: &str) -> Result<u32, SMCError> {
+    if key.len() != 4 || !key.is_ascii() {
+        return Err(SMCError::InvalidKey(key.to_string()));
+    }
+    Ok(str_to_u32(key))
+}
+
+fn str_to_u32(s: &str) -> u32 {
+    let bytes = s.as_bytes();
+    ((bytes[0] as u32) << 24)
+        | ((bytes[1] as u32) << 16)
+        | ((bytes[2] as u32) << 8)
+        | (bytes[3] as u32)
+}
+
+fn u32_to_bytes(val: u32) -> [u8; 4] {
+    [
+        (val >> 24) as u8,
+        (val >> 16) as u8,
+        (val >> 8) as u8,
+        val as u8,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ty(s: &str) -> SMCType {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_types() {
+        assert_eq!(ty("ui16"), SMCType::UInt);
+        assert_eq!(ty("si8"), SMCType::SInt);
+        assert_eq!(ty("fpe2"), SMCType::Fixed {
+            signed: false,
+            fraction_bits: 2
+        });
+        assert_eq!(ty("sp78"), SMCType::Fixed {
+            signed: true,
+            fraction_bits: 8
+        });
+        assert!("fpé".parse::<SMCType>().is_err());
+        assert!("fpzz".parse::<SMCType>().is_err());
+    }
+
+    #[test]
+    fn decodes_integers_in_both_byte_orders() {
+        let le = SMCEndian::Little;
+        let be = SMCEndian::Big;
+        assert_eq!(SMCType::UInt.decode(&[0x2a], le), Some(SMCValue::Int(42)));
+        assert_eq!(
+            SMCType::UInt.decode(&[0x34, 0x12], le),
+            Some(SMCValue::Int(0x1234))
+        );
+        assert_eq!(
+            SMCType::UInt.decode(&[0x12, 0x34], be),
+            Some(SMCValue::Int(0x1234))
+        );
+        assert_eq!(
+            SMCType::UInt.decode(&[0, 0, 0x01, 0x2c], be),
+            Some(SMCValue::Int(300))
+        );
+        assert_eq!(SMCType::SInt.decode(&[0xff], le), Some(SMCValue::Int(-1)));
+        assert_eq!(
+            SMCType::SInt.decode(&[0xff, 0xfe], be),
+            Some(SMCValue::Int(-2))
+        );
+        assert_eq!(
+            SMCType::SInt.decode(&(-5i64).to_le_bytes(), le),
+            Some(SMCValue::Int(-5))
+        );
+        assert_eq!(SMCType::UInt.decode(&[1, 2, 3], le), None);
+    }
+
+    #[test]
+    fn decodes_fixed_and_floats() {
+        assert_eq!(
+            ty("fpe2").decode(&[0x00, 0x0a], SMCEndian::Little),
+            Some(SMCValue::Float(2.5))
+        );
+        assert_eq!(
+            ty("sp78").decode(&[0xff, 0x80], SMCEndian::Little),
+            Some(SMCValue::Float(-0.5))
+        );
+        assert_eq!(
+            SMCType::FLT.decode(&1.5f32.to_le_bytes(), SMCEndian::Big),
+            Some(SMCValue::Float(1.5))
+        );
+        assert_eq!(
+            SMCType::IOFT.decode(&(3u64 << 16).to_le_bytes(), SMCEndian::Little),
+            Some(SMCValue::Float(3.))
+        );
+        assert_eq!(
+            SMCType::FLAG.decode(&[1], SMCEndian::Little),
+            Some(SMCValue::Bool(true))
+        );
+        assert_eq!(
+            SMCType::CH8.decode(b"M1\0\0", SMCEndian::Little),
+            Some(SMCValue::Str("M1".into()))
+        );
+    }
+
+    #[test]
+    fn round_trips_every_type_and_width() {
+        let cases: &[(&str, &[usize], &[f32])] = &[
+            ("ui8", &[1, 2, 4, 8], &[0., 1., 200.]),
+            ("si8", &[1, 2, 4, 8], &[-100., 0., 100.]),
+            ("fpe2", &[2, 4], &[0., 2.25, 1000.5]),
+            ("sp78", &[2, 4], &[-12.5, 0., 42.75]),
+            ("flt ", &[4], &[-3.5, 0., 1234.25]),
+            ("ioft", &[8], &[0., 1.5, 4096.25]),
+            ("flag", &[1], &[0., 1.]),
+        ];
+        for endian in [SMCEndian::Little, SMCEndian::Big] {
+            for &(name, sizes, values) in cases {
+                let ty = ty(name.trim());
+                for &size in sizes {
+                    for &value in values {
+                        let bytes = ty.encode(value, size, endian).unwrap();
+                        assert_eq!(bytes.len(), size, "{name} {size}");
+                        let decoded = ty.decode(&bytes, endian).unwrap().as_f32();
+                        assert_eq!(decoded, Some(value), "{name} {size} {endian:?}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn refuses_values_that_do_not_fit() {
+        let le = SMCEndian::Little;
+        assert_eq!(SMCType::UInt.encode(300., 1, le), None);
+        assert_eq!(SMCType::UInt.encode(-1., 2, le), None);
+        assert_eq!(SMCType::SInt.encode(128., 1, le), None);
+        assert_eq!(SMCType::SInt.encode(-129., 1, le), None);
+        assert_eq!(SMCType::UInt.encode(255., 1, le), Some(vec![0xff]));
+        assert_eq!(SMCType::SInt.encode(-128., 1, le), Some(vec![0x80]));
+        assert_eq!(ty("fpe2").encode(-1., 2, le), None);
+        assert_eq!(ty("fpe2").encode(20000., 2, le), None);
+        assert_eq!(SMCType::UInt.encode(f32::NAN, 4, le), None);
+        assert_eq!(SMCType::UInt.encode(1., 3, le), None);
+        assert_eq!(SMCType::CH8.encode(1., 4, le), None);
+    }
+
+    #[test]
+    fn lists_keys_by_prefix() {
+        let smc = MemorySMC::new()
+            .with_key("F0Ac", "flt ", &1200f32.to_le_bytes())
+            .unwrap()
+            .with_key("FNum", "ui8", &[1])
+            .unwrap()
+            .with_key("TB0T", "flt ", &30f32.to_le_bytes())
+            .unwrap();
+        let mut conn = SMCConnection::with_transport(smc);
+
+        let keys = conn.keys().unwrap();
+        assert_eq!(keys.len(), 3);
+        assert_eq!(keys[0].key, "F0Ac");
+        assert_eq!(keys[0].data_type, "flt");
+        assert_eq!(keys[0].value, Some(SMCValue::Float(1200.)));
+
+        let fans = conn.keys_matching("F").unwrap();
+        assert_eq!(fans.iter().map(|e| e.key.as_str()).collect::<Vec<_>>(), [
+            "F0Ac", "FNum"
+        ]);
+    }
+
+    #[test]
+    fn key_count_agrees_with_key_description() {
+        for endian in [SMCEndian::Little, SMCEndian::Big] {
+            let smc = MemorySMC::new()
+                .with_endian(endian)
+                .with_key("FNum", "ui8", &[1])
+                .unwrap()
+                .with_key("TB0T", "flt ", &30f32.to_le_bytes())
+                .unwrap();
+            let mut conn = SMCConnection::with_transport(smc);
+            assert_eq!(conn.key_count().unwrap(), 2);
+            assert_eq!(
+                conn.describe_key("#KEY").unwrap().value,
+                Some(SMCValue::Int(2))
+            );
+        }
+    }
+
+    #[test]
+    fn reads_the_key_count_big_endian() {
+        let smc = MemorySMC::new()
+            .with_endian(SMCEndian::Little)
+            .with_key("#KEY", "ui32", &2u32.to_be_bytes())
+            .unwrap()
+            .with_key("FNum", "ui8", &[1])
+            .unwrap();
+        let mut conn = SMCConnection::with_transport(smc);
+
+        assert_eq!(conn.key_count().unwrap(), 2);
+        assert_eq!(
+            conn.keys()
+                .unwrap()
+                .iter()
+                .map(|e| e.key.as_str())
+                .collect::<Vec<_>>(),
+            ["#KEY", "FNum"]
+        );
+    }
+
+    #[test]
+    fn stops_listing_past_the_end_of_the_table() {
+        // e.g. a count decoded in the wrong byte order
+        let smc = MemorySMC::new()
+            .with_key("#KEY", "ui32", &u32::MAX.to_be_bytes())
+            .unwrap()
+            .with_key("FNum", "ui8", &[1])
+            .unwrap();
+        let mut conn = SMCConnection::with_transport(smc);
+
+        assert_eq!(conn.key_count().unwrap(), u32::MAX);
+        assert_eq!(conn.keys_matching("F").unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,275 @@
+//! Which SMC keys to read for [`SMCPowerData`] and [`ThermalData`], per
+//! hardware model.
+//!
+//! Profiles are loaded from TOML or JSON, see `profiles.toml` next to this
+//! file for the bundled set and the format.
+
+use std::{fs, io, path::Path};
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{SMCPowerData, ThermalData};
+
+const BUNDLED_PROFILES: &str = include_str!("profiles.toml");
+
+#[derive(Debug, Error)]
+pub enum ProfileError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("invalid TOML: {0}")]
+    Toml(#[from] toml::de::Error),
+
+    #[error("invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("unknown profile format, expected a .toml or .json file")]
+    UnknownFormat,
+
+    #[error("invalid SMC key {0:?}, keys are four ASCII characters")]
+    InvalidKey(String),
+}
+
+/// A field of [`SMCPowerData`] a profile can fill.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PowerField {
+    BatteryRate,
+    DeliveryRate,
+    SystemTotal,
+    Heatpipe,
+    Brightness,
+    FullChargeCapacity,
+    CurrentCapacity,
+    ChargingStatus,
+    TimeToEmpty,
+    TimeToFull,
+    Temperature,
+}
+
+impl PowerField {
+    pub fn get_mut(self, data: &mut SMCPowerData) -> &mut f32 {
+        match self {
+            PowerField::BatteryRate => &mut data.battery_rate,
+            PowerField::DeliveryRate => &mut data.delivery_rate,
+            PowerField::SystemTotal => &mut data.system_total,
+            PowerField::Heatpipe => &mut data.heatpipe,
+            PowerField::Brightness => &mut data.brightness,
+            PowerField::FullChargeCapacity => &mut data.full_charge_capacity,
+            PowerField::CurrentCapacity => &mut data.current_capacity,
+            PowerField::ChargingStatus => &mut data.charging_status,
+            PowerField::TimeToEmpty => &mut data.time_to_empty,
+            PowerField::TimeToFull => &mut data.time_to_full,
+            PowerField::Temperature => &mut data.temperature,
+        }
+    }
+}
+
+/// A temperature of [`ThermalData`] a profile can fill.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ThermalField {
+    Cpu,
+    Gpu,
+    Soc,
+    Ambient,
+}
+
+impl ThermalField {
+    pub fn get_mut(self, data: &mut ThermalData) -> &mut Option<f32> {
+        match self {
+            ThermalField::Cpu => &mut data.cpu,
+            ThermalField::Gpu => &mut data.gpu,
+            ThermalField::Soc => &mut data.soc,
+            ThermalField::Ambient => &mut data.ambient,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SensorProfile {
+    pub name: String,
+    /// Prefixes of `hw.model` this profile applies to, e.g. `MacBookPro16,`.
+    /// A profile without any applies to every machine.
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// Keys to try for each field, in order, until one can be read.
+    pub sensors: IndexMap<PowerField, Vec<String>>,
+    /// Temperature keys for each thermal field, the readable ones are
+    /// averaged, e.g. over every CPU core.
+    #[serde(default)]
+    pub thermal: IndexMap<ThermalField, Vec<String>>,
+}
+
+impl SensorProfile {
+    pub fn matches(&self, model: &str) -> bool {
+        self.models.is_empty() || self.models.iter().any(|m| model.starts_with(m.as_str()))
+    }
+
+    fn validate(&self) -> Result<(), ProfileError> {
+        match self
+            .sensors
+            .values()
+            .chain(self.thermal.values())
+            .flatten()
+            .find(|key| key.len() != 4 || !key.is_ascii())
+        {
+            Some(key) => Err(ProfileError::InvalidKey(key.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SensorProfiles {
+    pub profiles: Vec<SensorProfile>,
+}
+
+impl SensorProfiles {
+    /// The profiles shipped with tpower.
+    pub fn bundled() -> Self {
+        Self::from_toml(BUNDLED_PROFILES).expect("bundled profiles are valid")
+    }
+
+    pub fn from_toml(s: &str) -> Result<Self, ProfileError> {
+        let profiles: Self = toml::from_str(s)?;
+        profiles.validate()
+    }
+
+    pub fn from_json(s: &str) -> Result<Self, ProfileError> {
+        let profiles: Self = serde_json::from_str(s)?;
+        profiles.validate()
+    }
+
+    /// Load a `.toml` or `.json` file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ProfileError> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Self::from_toml(&content),
+            Some("json") => Self::from_json(&content),
+            _ => Err(ProfileError::UnknownFormat),
+        }
+    }
+
+    /// These profiles, then `fallback` for the machines none of them
+    /// applies to.
+    #[must_use]
+    pub fn with_fallback(mut self, fallback: Self) -> Self {
+        self.profiles.extend(fallback.profiles);
+        self
+    }
+
+    /// The first profile that applies to `model`.
+    pub fn for_model(&self, model: &str) -> Option<&SensorProfile> {
+        self.profiles.iter().find(|p| p.matches(model))
+    }
+
+    fn validate(self) -> Result<Self, ProfileError> {
+        self.profiles.iter().try_for_each(SensorProfile::validate)?;
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ffi::smc::{MemorySMC, SMCConnection, SMCEndian, SMCReadSensor};
+
+    fn name<'a>(profiles: &'a SensorProfiles, model: &str) -> Option<&'a str> {
+        profiles.for_model(model).map(|p| p.name.as_str())
+    }
+
+    #[test]
+    fn parses_the_bundled_profiles() {
+        let profiles = SensorProfiles::bundled();
+        assert!(profiles.profiles.iter().all(|p| !p.sensors.is_empty()));
+        // the catch-all comes last, or it would shadow the rest
+        let catch_all = profiles.profiles.iter().position(|p| p.models.is_empty());
+        assert_eq!(catch_all, Some(profiles.profiles.len() - 1));
+    }
+
+    #[test]
+    fn picks_a_profile_by_model() {
+        let profiles = SensorProfiles::bundled();
+        assert_eq!(name(&profiles, "MacBookPro16,1"), Some("intel"));
+        assert_eq!(name(&profiles, "MacBookAir8,2"), Some("intel"));
+        assert_eq!(name(&profiles, "MacBookPro18,3"), Some("m1"));
+        assert_eq!(name(&profiles, "Mac14,2"), Some("m2"));
+        assert_eq!(name(&profiles, "Mac15,3"), Some("m3"));
+        assert_eq!(name(&profiles, "Mac16,1"), Some("apple-silicon"));
+        assert_eq!(name(&profiles, ""), Some("apple-silicon"));
+    }
+
+    #[test]
+    fn leaves_the_heatpipe_out_on_intel() {
+        let profiles = SensorProfiles::bundled();
+        let intel = profiles.for_model("MacBookPro16,1").unwrap();
+        assert!(!intel.sensors.contains_key(&PowerField::Heatpipe));
+
+        let smc = MemorySMC::new()
+            .with_endian(SMCEndian::Big)
+            .with_key("PCPC", "flt ", &20f32.to_le_bytes())
+            .unwrap()
+            .with_key("TB1T", "sp78", &[0x1e, 0x80])
+            .unwrap();
+        let power = SMCConnection::with_transport(smc).read_sensor(intel);
+        assert_eq!(power.heatpipe, 0.);
+        // falls through the missing TB0T
+        assert_eq!(power.temperature, 30.5);
+    }
+
+    #[test]
+    fn falls_back_to_the_next_profile() {
+        let user = SensorProfiles::from_toml(
+            r#"
+            [[profiles]]
+            name = "mine"
+            models = ["Mac99,"]
+
+            [profiles.sensors]
+            systemTotal = ["PSTX", "PSTR"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(user.profiles[0].sensors[&PowerField::SystemTotal], [
+            "PSTX", "PSTR"
+        ]);
+
+        let profiles = user.with_fallback(SensorProfiles::bundled());
+        assert_eq!(name(&profiles, "Mac99,1"), Some("mine"));
+        assert_eq!(name(&profiles, "MacBookPro16,1"), Some("intel"));
+    }
+
+    #[test]
+    fn refuses_invalid_keys() {
+        let toml = r#"
+            [[profiles]]
+            name = "broken"
+
+            [profiles.sensors]
+            systemTotal = ["PSTR"]
+
+            [profiles.thermal]
+            cpu = ["TC0PX"]
+        "#;
+        assert!(matches!(
+            SensorProfiles::from_toml(toml),
+            Err(ProfileError::InvalidKey(key)) if key == "TC0PX"
+        ));
+
+        let json = r#"{"profiles": [{"name": "broken", "sensors": {"systemTotal": ["PST"]}}]}"#;
+        assert!(matches!(
+            SensorProfiles::from_json(json),
+            Err(ProfileError::InvalidKey(key)) if key == "PST"
+        ));
+        assert!(matches!(
+            SensorProfiles::from_toml("[[profiles]]"),
+            Err(ProfileError::Toml(_))
+        ));
+    }
+}
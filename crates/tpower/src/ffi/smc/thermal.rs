@@ -0,0 +1,89 @@
+//! Temperatures and fans, to put the power readings next to the heat.
+
+use serde::{Deserialize, Serialize};
+
+use super::{SMCConnection, SMCTransport, SensorProfile};
+
+/// Readings outside of this range (°C) come from sensors that are absent or
+/// not powered and are ignored.
+const PLAUSIBLE_TEMPERATURE: std::ops::Range<f32> = 0.1..150.;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[serde(rename_all = "camelCase")]
+pub struct ThermalData {
+    /// °C, `None` if the profile has no readable key for it
+    pub cpu: Option<f32>,
+    pub gpu: Option<f32>,
+    pub soc: Option<f32>,
+    pub ambient: Option<f32>,
+    /// Empty on fanless machines
+    pub fans: Vec<FanData>,
+}
+
+/// Fan speeds in RPM.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[serde(rename_all = "camelCase")]
+pub struct FanData {
+    pub actual: f32,
+    pub min: f32,
+    pub max: f32,
+    pub target: f32,
+}
+
+pub trait SMCReadThermal {
+    fn read_thermal(&mut self, profile: &SensorProfile) -> ThermalData;
+}
+
+impl<T: SMCTransport> SMCReadThermal for SMCConnection<T> {
+    fn read_thermal(&mut self, profile: &SensorProfile) -> ThermalData {
+        let mut data =
+            profile
+                .thermal
+                .iter()
+                .fold(ThermalData::default(), |mut acc, (field, keys)| {
+                    let readings = keys
+                        .iter()
+                        .filter_map(|key| self.read_key(key).and_then(|val| val.value()).ok())
+                        .filter(|val| PLAUSIBLE_TEMPERATURE.contains(val))
+                        .collect::<Vec<_>>();
+                    if !readings.is_empty() {
+                        *field.get_mut(&mut acc) =
+                            Some(readings.iter().sum::<f32>() / readings.len() as f32);
+                    }
+                    acc
+                });
+
+        // fan keys have room for a single digit
+        data.fans = (0..self.fan_count().min(10))
+            .map_while(|fan| self.read_fan(fan))
+            .collect();
+        data
+    }
+}
+
+impl<T: SMCTransport> SMCConnection<T> {
+    /// Number of fans, 0 if the SMC doesn't know about any.
+    pub fn fan_count(&mut self) -> u8 {
+        self.read_key("FNum")
+            .and_then(|val| val.value())
+            .map_or(0, |n| n as u8)
+    }
+
+    /// Speeds of fan `fan`, `None` if its actual speed can't be read.
+    pub fn read_fan(&mut self, fan: u8) -> Option<FanData> {
+        let mut read = |suffix: &str| {
+            self.read_key(&format!("F{fan}{suffix}"))
+                .and_then(|val| val.value())
+                .ok()
+        };
+
+        Some(FanData {
+            actual: read("Ac")?,
+            min: read("Mn").unwrap_or_default(),
+            max: read("Mx").unwrap_or_default(),
+            target: read("Tg").unwrap_or_default(),
+        })
+    }
+}
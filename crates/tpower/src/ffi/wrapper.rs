@@ -1,10 +1,10 @@
 use std::ptr::{null, null_mut};
 
 use core_foundation::{
-    base::TCFType,
+    base::{CFType, CFTypeRef, TCFType},
     dictionary::CFDictionaryRef,
     propertylist::kCFPropertyListXMLFormat_v1_0,
-    string::{CFString, CFStringRef},
+    string::CFString,
 };
 use scopefn::Run;
 
@@ -26,23 +26,23 @@ unsafe impl Send for ServiceConnection {}
 unsafe impl Sync for ServiceConnection {}
 
 impl ServiceConnection {
-    fn start(device: AMDeviceRef, service_name: &str) -> Self {
+    fn start(device: AMDeviceRef, service_name: &str) -> Result<Self, DeviceError> {
         unsafe {
             let service_name = cfstr!(service_name);
-            let service_ptr: AMDServiceConnectionRef = null_mut();
+            let mut service_ptr: AMDServiceConnectionRef = null_mut();
 
             let result = AMDeviceSecureStartService(
                 device,
                 service_name.as_concrete_TypeRef(),
                 null_mut(),
-                &service_ptr,
+                &mut service_ptr,
             );
 
-            if result != 0 {
-                panic!("couldn't start service {}", result);
+            if result != 0 || service_ptr.is_null() {
+                return Err(DeviceError::StartService(service_name.to_string(), result));
             }
 
-            ServiceConnection(service_ptr)
+            Ok(ServiceConnection(service_ptr))
         }
     }
 
@@ -99,6 +99,13 @@ pub enum DeviceError {
 
     #[error("session failed: {0}")]
     Session(i32),
+
+    #[error("couldn't start service {0}: {1}")]
+    StartService(String, i32),
+
+    /// e.g. the device is locked or doesn't trust this Mac yet
+    #[error("missing value {0}")]
+    MissingValue(&'static str),
 }
 
 impl Device {
@@ -115,16 +122,22 @@ impl Device {
         }
     }
 
-    pub fn name(&self) -> String {
+    pub fn name(&self) -> Result<String, DeviceError> {
         let name = unsafe {
             AMDeviceCopyValue(
                 self.device,
                 null(),
                 cfstr!("DeviceName").as_concrete_TypeRef(),
             )
-        } as CFStringRef;
+        } as CFTypeRef;
+        if name.is_null() {
+            return Err(DeviceError::MissingValue("DeviceName"));
+        }
 
-        unsafe { CFString::wrap_under_create_rule(name) }.to_string()
+        unsafe { CFType::wrap_under_create_rule(name) }
+            .downcast_into::<CFString>()
+            .map(|name| name.to_string())
+            .ok_or(DeviceError::MissingValue("DeviceName"))
     }
 
     pub fn interface_type(&mut self) -> InterfaceType {
@@ -188,7 +201,7 @@ impl Device {
         Ok(())
     }
 
-    pub fn start_service(&self, service_name: &str) -> ServiceConnection {
+    pub fn start_service(&self, service_name: &str) -> Result<ServiceConnection, DeviceError> {
         ServiceConnection::start(self.device, service_name)
     }
 }
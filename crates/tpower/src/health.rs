@@ -0,0 +1,256 @@
+//! Battery state of health, how fast it fades and when it will cross a
+//! service threshold.
+//!
+//! Everything is in percent of the design capacity. Fades are fitted over
+//! the whole history so the noise of single gas gauge readings averages out.
+
+use serde::{Deserialize, Serialize};
+
+use crate::provider::NormalizedResource;
+
+/// Apple considers a battery due for service below 80%.
+pub const DEFAULT_THRESHOLDS: [f32; 1] = [80.];
+
+const SECS_PER_DAY: f64 = 86400.;
+
+/// `max_capacity` in percent of `design_capacity`, `None` if either isn't
+/// reported.
+pub fn state_of_health(max_capacity: i32, design_capacity: i32) -> Option<f32> {
+    (max_capacity > 0 && design_capacity > 0)
+        .then(|| max_capacity as f32 / design_capacity as f32 * 100.)
+}
+
+/// The capacity of a battery at one point in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[serde(rename_all = "camelCase")]
+pub struct CapacitySample {
+    /// Seconds since the unix epoch
+    pub timestamp: i64,
+    pub cycle_count: i32,
+    /// mAh
+    pub max_capacity: i32,
+    /// mAh
+    pub design_capacity: i32,
+}
+
+impl CapacitySample {
+    pub fn state_of_health(&self) -> Option<f32> {
+        state_of_health(self.max_capacity, self.design_capacity)
+    }
+}
+
+impl From<&NormalizedResource> for CapacitySample {
+    fn from(res: &NormalizedResource) -> Self {
+        Self {
+            timestamp: res.last_update,
+            cycle_count: res.cycle_count,
+            max_capacity: res.max_capacity,
+            design_capacity: res.design_capacity,
+        }
+    }
+}
+
+/// When the state of health reaches `threshold` at the current fade.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[serde(rename_all = "camelCase")]
+pub struct HealthProjection {
+    pub threshold: f32,
+    /// Already at or below the threshold
+    pub reached: bool,
+    pub cycle_count: Option<i32>,
+    /// Seconds since the unix epoch
+    pub timestamp: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[serde(rename_all = "camelCase")]
+pub struct HealthReport {
+    /// The latest sample
+    pub current: CapacitySample,
+    pub state_of_health: f32,
+    /// Percentage points lost per cycle, `None` until the history spans more
+    /// than one cycle count
+    pub fade_per_cycle: Option<f32>,
+    /// Percentage points lost per day, `None` until the history spans more
+    /// than a day
+    pub fade_per_day: Option<f32>,
+    pub projections: Vec<HealthProjection>,
+    /// Samples the fades were fitted over
+    pub samples: usize,
+}
+
+impl HealthReport {
+    /// Analyze `history` in any order, samples without capacities are
+    /// skipped. `None` if there is no usable sample.
+    pub fn new(
+        history: impl IntoIterator<Item = CapacitySample>,
+        thresholds: &[f32],
+    ) -> Option<Self> {
+        let mut history = history
+            .into_iter()
+            .filter_map(|sample| Some((sample, sample.state_of_health()? as f64)))
+            .collect::<Vec<_>>();
+        history.sort_by_key(|(sample, _)| sample.timestamp);
+
+        let &(current, state_of_health) = history.last()?;
+
+        let fade_per_cycle = fit_slope(
+            history
+                .iter()
+                .map(|(sample, soh)| (sample.cycle_count as f64, *soh)),
+        )
+        .map(|slope| -slope as f32);
+        let fade_per_day = fit_slope(
+            history
+                .iter()
+                .map(|(sample, soh)| (sample.timestamp as f64 / SECS_PER_DAY, *soh)),
+        )
+        .map(|slope| -slope as f32);
+
+        let state_of_health = state_of_health as f32;
+        let projections = thresholds
+            .iter()
+            .map(|&threshold| {
+                let remaining = state_of_health - threshold;
+                let ahead = |fade: Option<f32>| {
+                    fade.filter(|&fade| remaining > 0. && fade > 0.)
+                        .map(|fade| (remaining / fade) as f64)
+                };
+                HealthProjection {
+                    threshold,
+                    reached: remaining <= 0.,
+                    cycle_count: ahead(fade_per_cycle)
+                        .map(|cycles| current.cycle_count + cycles.ceil() as i32),
+                    timestamp: ahead(fade_per_day)
+                        .map(|days| current.timestamp + (days * SECS_PER_DAY) as i64),
+                }
+            })
+            .collect();
+
+        Some(Self {
+            current,
+            state_of_health,
+            fade_per_cycle,
+            fade_per_day,
+            projections,
+            samples: history.len(),
+        })
+    }
+}
+
+/// Least squares slope of `points`, `None` if their x values span less than
+/// one cycle or day.
+fn fit_slope(points: impl Iterator<Item = (f64, f64)> + Clone) -> Option<f64> {
+    let (min, max) = points
+        .clone()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), (x, _)| {
+            (min.min(x), max.max(x))
+        });
+    if max - min < 1. {
+        return None;
+    }
+
+    let n = points.clone().count() as f64;
+    let (sum_x, sum_y) = points
+        .clone()
+        .fold((0., 0.), |(sx, sy), (x, y)| (sx + x, sy + y));
+    let (mean_x, mean_y) = (sum_x / n, sum_y / n);
+    let (cov, var) = points.fold((0., 0.), |(cov, var), (x, y)| {
+        (
+            cov + (x - mean_x) * (y - mean_y),
+            var + (x - mean_x).powi(2),
+        )
+    });
+    Some(cov / var)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = SECS_PER_DAY as i64;
+
+    fn sample(day: i64, cycle_count: i32, max_capacity: i32) -> CapacitySample {
+        CapacitySample {
+            timestamp: 1_700_000_000 + day * DAY,
+            cycle_count,
+            max_capacity,
+            design_capacity: 5000,
+        }
+    }
+
+    #[test]
+    fn computes_state_of_health() {
+        assert_eq!(state_of_health(4000, 5000), Some(80.));
+        assert_eq!(state_of_health(5100, 5000), Some(102.));
+        assert_eq!(state_of_health(4000, 0), None);
+        assert_eq!(state_of_health(0, 5000), None);
+    }
+
+    #[test]
+    fn fits_the_fade_and_projects_the_threshold() {
+        // 2% of 5000 mAh every 10 cycles and 20 days, given out of order
+        let history = [
+            sample(40, 20, 4800),
+            sample(0, 0, 5000),
+            sample(20, 10, 4900),
+        ];
+        let report = HealthReport::new(history, &[80., 99.]).unwrap();
+
+        assert_eq!(report.current, sample(40, 20, 4800));
+        assert_eq!(report.state_of_health, 96.);
+        assert_eq!(report.samples, 3);
+        assert!((report.fade_per_cycle.unwrap() - 0.2).abs() < 1e-4);
+        assert!((report.fade_per_day.unwrap() - 0.1).abs() < 1e-4);
+
+        let [service, reached] = report.projections.as_slice() else {
+            panic!("one projection per threshold");
+        };
+        // 16 points to go
+        assert!(!service.reached);
+        assert_eq!(service.cycle_count, Some(100));
+        let days = (service.timestamp.unwrap() - report.current.timestamp) as f64 / DAY as f64;
+        assert!((days - 160.).abs() < 0.01);
+
+        assert!(reached.reached);
+        assert_eq!(reached.cycle_count, None);
+        assert_eq!(reached.timestamp, None);
+    }
+
+    #[test]
+    fn needs_a_span_to_fit_a_fade() {
+        let report = HealthReport::new([sample(0, 10, 4500)], &DEFAULT_THRESHOLDS).unwrap();
+
+        assert_eq!(report.state_of_health, 90.);
+        assert_eq!(report.fade_per_cycle, None);
+        assert_eq!(report.fade_per_day, None);
+        assert_eq!(report.projections[0].cycle_count, None);
+        assert!(!report.projections[0].reached);
+    }
+
+    #[test]
+    fn does_not_project_a_battery_that_is_not_fading() {
+        let history = [sample(0, 0, 4900), sample(30, 15, 4950)];
+        let report = HealthReport::new(history, &DEFAULT_THRESHOLDS).unwrap();
+
+        assert!(report.fade_per_cycle.unwrap() < 0.);
+        assert_eq!(report.projections[0].cycle_count, None);
+        assert_eq!(report.projections[0].timestamp, None);
+    }
+
+    #[test]
+    fn skips_samples_without_capacities() {
+        let unknown = CapacitySample {
+            design_capacity: 0,
+            ..sample(50, 30, 4000)
+        };
+        let report = HealthReport::new([sample(0, 0, 5000), unknown], &[]).unwrap();
+
+        assert_eq!(report.samples, 1);
+        assert_eq!(report.current, sample(0, 0, 5000));
+        assert!(HealthReport::new([unknown], &[]).is_none());
+    }
+}
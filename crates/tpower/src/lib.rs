@@ -1,5 +1,12 @@
+pub mod charge;
 pub mod de;
+pub mod estimate;
+pub mod export;
 pub mod ffi;
+pub mod health;
+#[cfg(apple_ffi)]
 pub mod macros;
+pub mod phase;
 pub mod provider;
+pub mod trace;
 pub mod util;
@@ -0,0 +1,249 @@
+//! Split a charging session into the phases of a Li-ion charge.
+//!
+//! The charger pushes a constant current until the cell reaches its
+//! charge voltage, then holds the voltage while the current tapers and
+//! finally trickles into the top few percent. macOS may hold off in between
+//! for optimized charging, the battery then draws next to nothing.
+//!
+//! Phases are told apart by the battery power relative to the plateau of
+//! the session, smoothed over a few samples so single spikes don't split it.
+
+use serde::{Deserialize, Serialize};
+
+use crate::provider::NormalizedResource;
+
+/// Samples the battery power is smoothed over.
+const WINDOW: usize = 5;
+/// Below this the battery is held, W.
+const HOLD_POWER: f32 = 0.5;
+/// Share of the plateau the power stays above in constant current.
+const CONSTANT_CURRENT: f32 = 0.85;
+/// Share of the plateau below which the charge trickles.
+const TRICKLE: f32 = 0.1;
+/// Trickling only happens this close to full.
+const TRICKLE_LEVEL: i32 = 90;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[serde(rename_all = "camelCase")]
+pub enum ChargingPhase {
+    ConstantCurrent,
+    /// The current tapers off
+    ConstantVoltage,
+    Trickle,
+    /// Charging paused, e.g. by optimized charging
+    Hold,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[serde(rename_all = "camelCase")]
+pub struct PhaseSpan {
+    pub phase: ChargingPhase,
+    /// Seconds since the unix epoch
+    pub start: i64,
+    pub end: i64,
+    pub from_level: i32,
+    pub end_level: i32,
+    /// W
+    pub avg_power: f32,
+}
+
+/// The phases of `curve` in order, samples are expected to be sorted by
+/// time.
+pub fn detect_phases(curve: &[NormalizedResource]) -> Vec<PhaseSpan> {
+    let power = smoothed_power(curve);
+    let plateau = power.iter().copied().fold(0., f32::max);
+    if plateau < HOLD_POWER {
+        return span(ChargingPhase::Hold, curve).into_iter().collect();
+    }
+
+    // the charger only moves forward, a hold may interrupt it at any point.
+    // Until the plateau is first reached, the power is still ramping up
+    let mut reached = false;
+    let mut stage = ChargingPhase::ConstantCurrent;
+    let phases = curve
+        .iter()
+        .zip(&power)
+        .map(|(res, &power)| {
+            if power < HOLD_POWER {
+                return ChargingPhase::Hold;
+            }
+            let next = if power >= plateau * CONSTANT_CURRENT {
+                ChargingPhase::ConstantCurrent
+            } else if power < plateau * TRICKLE && res.battery_level >= TRICKLE_LEVEL {
+                ChargingPhase::Trickle
+            } else {
+                ChargingPhase::ConstantVoltage
+            };
+            reached |= next == ChargingPhase::ConstantCurrent;
+            if reached {
+                stage = stage.max(next);
+            }
+            stage
+        })
+        .collect::<Vec<_>>();
+
+    let mut spans = vec![];
+    let mut start = 0;
+    for (i, phase) in phases.iter().enumerate() {
+        if phases.get(i + 1) != Some(phase) {
+            spans.extend(span(*phase, &curve[start..=i]));
+            start = i + 1;
+        }
+    }
+    spans
+}
+
+fn span(phase: ChargingPhase, samples: &[NormalizedResource]) -> Option<PhaseSpan> {
+    let (first, last) = (samples.first()?, samples.last()?);
+    Some(PhaseSpan {
+        phase,
+        start: first.last_update,
+        end: last.last_update,
+        from_level: first.battery_level,
+        end_level: last.battery_level,
+        avg_power: samples.iter().map(|res| res.battery_power).sum::<f32>() / samples.len() as f32,
+    })
+}
+
+/// Moving median of the battery power, centered on each sample.
+fn smoothed_power(curve: &[NormalizedResource]) -> Vec<f32> {
+    (0..curve.len())
+        .map(|i| {
+            let from = i.saturating_sub(WINDOW / 2);
+            let to = (i + WINDOW / 2 + 1).min(curve.len());
+            let mut window = curve[from..to]
+                .iter()
+                .map(|res| res.battery_power)
+                .collect::<Vec<_>>();
+            window.sort_by(f32::total_cmp);
+            window[window.len() / 2]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::NormalizedData;
+
+    fn sample(minute: i64, battery_level: i32, battery_power: f32) -> NormalizedResource {
+        NormalizedResource {
+            is_charging: true,
+            last_update: 1_700_000_000 + minute * 60,
+            data: NormalizedData {
+                battery_level,
+                battery_power,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Constant current, a hold, the taper and the trickle, `(phase, first
+    /// minute, last minute)` of each as charged.
+    fn session() -> (Vec<NormalizedResource>, [(ChargingPhase, i64, i64); 5]) {
+        let mut curve = vec![];
+        // ramping up, then the plateau with a dropout the median smooths over
+        for minute in 0..20 {
+            let power = match minute {
+                0 => 20.,
+                7 => 0.,
+                _ => 40.,
+            };
+            curve.push(sample(minute, 20 + minute as i32 * 2, power));
+        }
+        // held at 60% by optimized charging
+        for minute in 20..30 {
+            curve.push(sample(minute, 60, 0.1));
+        }
+        // back on the plateau for a bit before the voltage limit is hit
+        for minute in 30..35 {
+            curve.push(sample(minute, 60 + (minute - 30) as i32, 40.));
+        }
+        for minute in 35..50 {
+            let power = 30. - (minute - 35) as f32 * 1.5;
+            curve.push(sample(minute, 65 + (minute - 35) as i32 * 2, power));
+        }
+        for minute in 50..60 {
+            curve.push(sample(minute, 90 + (minute - 50) as i32, 2.));
+        }
+
+        let expected = [
+            (ChargingPhase::ConstantCurrent, 0, 19),
+            (ChargingPhase::Hold, 20, 29),
+            (ChargingPhase::ConstantCurrent, 30, 34),
+            (ChargingPhase::ConstantVoltage, 35, 49),
+            (ChargingPhase::Trickle, 50, 59),
+        ];
+        (curve, expected)
+    }
+
+    #[test]
+    fn splits_a_session_into_phases() {
+        let (curve, expected) = session();
+        let spans = detect_phases(&curve);
+
+        let phases = spans.iter().map(|s| s.phase).collect::<Vec<_>>();
+        assert_eq!(phases, expected.map(|(phase, ..)| phase));
+        for (span, (phase, first, last)) in spans.iter().zip(expected) {
+            // the median may move a boundary by half its window
+            let slack = (WINDOW / 2) as i64 * 60;
+            assert!(
+                (span.start - curve[first as usize].last_update).abs() <= slack,
+                "{phase:?} starts at {}",
+                span.start
+            );
+            assert!(
+                (span.end - curve[last as usize].last_update).abs() <= slack,
+                "{phase:?} ends at {}",
+                span.end
+            );
+        }
+
+        let first = spans.first().unwrap();
+        assert_eq!(first.start, curve[0].last_update);
+        assert_eq!(first.from_level, 20);
+        let last = spans.last().unwrap();
+        assert_eq!(last.end, curve[59].last_update);
+        assert_eq!(last.end_level, 99);
+        assert!((last.avg_power - 2.).abs() < 1e-6);
+        // spans cover the session without overlapping
+        for pair in spans.windows(2) {
+            assert_eq!(pair[1].start - pair[0].end, 60);
+        }
+    }
+
+    #[test]
+    fn never_steps_back_after_the_taper() {
+        let (mut curve, _) = session();
+        // a burst in the middle of the taper doesn't restart constant current
+        for res in &mut curve[40..43] {
+            res.data.battery_power = 40.;
+        }
+        let phases = detect_phases(&curve)
+            .iter()
+            .map(|s| s.phase)
+            .collect::<Vec<_>>();
+
+        assert_eq!(phases, [
+            ChargingPhase::ConstantCurrent,
+            ChargingPhase::Hold,
+            ChargingPhase::ConstantCurrent,
+            ChargingPhase::ConstantVoltage,
+            ChargingPhase::Trickle,
+        ]);
+    }
+
+    #[test]
+    fn holds_a_session_without_power() {
+        let curve = (0..10).map(|m| sample(m, 80, 0.2)).collect::<Vec<_>>();
+        let spans = detect_phases(&curve);
+
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].phase, ChargingPhase::Hold);
+        assert_eq!(spans[0].end - spans[0].start, 9 * 60);
+        assert!(detect_phases(&[]).is_empty());
+    }
+}
@@ -0,0 +1,114 @@
+use std::{ffi::CString, mem};
+
+use anyhow::bail;
+use core_foundation::{
+    base::{kCFAllocatorDefault, mach_port_t, TCFType},
+    dictionary::{CFDictionary, CFMutableDictionaryRef},
+};
+use io_kit_sys::{
+    ret::kIOReturnSuccess, IOMasterPort, IORegistryEntryCreateCFProperties,
+    IOServiceGetMatchingService, IOServiceMatching,
+};
+
+use super::{MergedPowerData, PowerDataFrom, PowerSource, SourceCapabilities};
+use crate::{
+    de::IORegistry,
+    ffi::smc::{
+        IOKitTransport, SMCConnection, SMCReadSensor, SMCReadThermal, SensorProfile, SensorProfiles,
+    },
+    util::{dict_into, get_hw_model},
+};
+
+pub fn get_mac_ioreg_dict() -> anyhow::Result<CFDictionary> {
+    let mut master_port: mach_port_t = 0;
+    if unsafe { IOMasterPort(0, &mut master_port) } != 0 {
+        bail!("could not get master port");
+    }
+    let name = CString::new("AppleSmartBattery").unwrap();
+    let matching_dict = unsafe { IOServiceMatching(name.as_ptr()) };
+
+    let result = unsafe { IOServiceGetMatchingService(master_port, matching_dict) };
+
+    let mut properties: CFMutableDictionaryRef = unsafe { mem::zeroed() };
+    if unsafe { IORegistryEntryCreateCFProperties(result, &mut properties, kCFAllocatorDefault, 0) }
+        != kIOReturnSuccess
+    {
+        bail!("could not get properties");
+    }
+
+    unsafe { Ok(CFDictionary::wrap_under_create_rule(properties)) }
+}
+
+pub fn get_mac_ioreg() -> anyhow::Result<IORegistry> {
+    let dic = get_mac_ioreg_dict()?;
+    Ok(IORegistry::from_value(&dict_into::<plist::Value>(dic)?)?)
+}
+
+/// Everything `AppleSmartBattery` reports, including the keys
+/// [`IORegistry`] doesn't model.
+pub fn get_mac_ioreg_raw() -> anyhow::Result<plist::Dictionary> {
+    let dic = get_mac_ioreg_dict()?;
+    Ok(dict_into::<plist::Dictionary>(dic)?)
+}
+
+/// The Mac this process is running on, read from `AppleSmartBattery` and the SMC.
+///
+/// Without access to the SMC, samples only carry what `AppleSmartBattery`
+/// reports, like those of a remote device.
+pub struct LocalSource {
+    smc: Option<SMCConnection<IOKitTransport>>,
+    profile: SensorProfile,
+}
+
+impl LocalSource {
+    /// Read the SMC with the bundled profile for this machine.
+    pub fn new() -> Self {
+        Self::with_profiles(&SensorProfiles::bundled())
+    }
+
+    /// Read the SMC with the first of `profiles` that applies to this machine.
+    pub fn with_profiles(profiles: &SensorProfiles) -> Self {
+        let model = get_hw_model().unwrap_or_default();
+        let profile = profiles.for_model(&model).cloned().unwrap_or_default();
+        log::debug!("Using SMC sensor profile {:?} for {model}", profile.name);
+
+        let smc = SMCConnection::new("AppleSMC")
+            .inspect_err(|err| log::warn!("SMC unavailable, sampling without it: {err}"))
+            .ok();
+        Self { smc, profile }
+    }
+
+    pub fn profile(&self) -> &SensorProfile {
+        &self.profile
+    }
+}
+
+impl Default for LocalSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PowerSource for LocalSource {
+    fn identity(&self) -> PowerDataFrom {
+        PowerDataFrom::Local
+    }
+
+    fn capabilities(&self) -> SourceCapabilities {
+        SourceCapabilities {
+            smc: self.smc.is_some(),
+            power_telemetry: true,
+            adapter_details: true,
+        }
+    }
+
+    fn sample(&mut self) -> anyhow::Result<MergedPowerData> {
+        let profile = &self.profile;
+        Ok(MergedPowerData {
+            from: PowerDataFrom::Local,
+            smc: self.smc.as_mut().map(|smc| smc.read_sensor(profile)),
+            ioreg: get_mac_ioreg()?,
+            thermal: self.smc.as_mut().map(|smc| smc.read_thermal(profile)),
+        })
+    }
+}
@@ -1,31 +1,32 @@
 use std::{
     collections::VecDeque,
-    ffi::CString,
-    mem,
     ops::{Deref, Div},
     time::Duration,
 };
 
-use anyhow::bail;
-use core_foundation::{
-    base::{kCFAllocatorDefault, mach_port_t, TCFType},
-    dictionary::{CFDictionary, CFMutableDictionaryRef},
-};
 use derive_more::Add;
-use io_kit_sys::{
-    ret::kIOReturnSuccess, IOMasterPort, IORegistryEntryCreateCFProperties,
-    IOServiceGetMatchingService, IOServiceMatching,
-};
 use ratatui::widgets::SparklineBar;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    de::{repr, IORegistry},
-    ffi::{smc::SMCPowerData, InterfaceType},
-    util::{dict_into, skip_until},
+    de::{BatteryData, ChargerData, IORegistry},
+    ffi::{
+        smc::{SMCPowerData, ThermalData},
+        InterfaceType,
+    },
+    util::skip_until,
 };
 
+#[cfg(apple_ffi)]
+pub mod local;
+#[cfg(apple_ffi)]
 pub mod remote;
+mod source;
+pub mod sysfs;
+
+#[cfg(apple_ffi)]
+pub use local::{get_mac_ioreg, get_mac_ioreg_dict, get_mac_ioreg_raw};
+pub use source::{PowerSource, SourceCapabilities};
 
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
 #[cfg_attr(feature = "specta", derive(specta::Type))]
@@ -41,8 +42,48 @@ pub struct NormalizedResource {
     pub max_capacity: i32,
     #[serde(default)]
     pub design_capacity: i32,
+    /// V
+    #[serde(default)]
+    pub voltage: f32,
+    /// Whether an adapter is attached, `None` if the battery doesn't say
+    #[serde(default)]
+    pub external_connected: Option<bool>,
     #[serde(flatten)]
     pub data: NormalizedData,
+    /// Only available for the local machine
+    #[serde(default)]
+    pub thermal: Option<ThermalData>,
+    /// Left out when serialized, it rarely changes and is too large to send
+    /// along with every tick
+    #[serde(skip)]
+    pub battery: BatteryDetails,
+}
+
+/// What the gas gauge and charger report beyond the power readings, each
+/// `None` if the battery doesn't report it.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[serde(rename_all = "camelCase")]
+pub struct BatteryDetails {
+    pub serial: Option<String>,
+    pub manufacture_date: Option<i64>,
+    pub installed: Option<bool>,
+    pub permanent_failure_status: Option<i64>,
+    pub battery_data: Option<BatteryData>,
+    pub charger_data: Option<ChargerData>,
+}
+
+impl From<&IORegistry> for BatteryDetails {
+    fn from(io: &IORegistry) -> Self {
+        Self {
+            serial: io.serial.clone(),
+            manufacture_date: io.manufacture_date,
+            installed: io.battery_installed,
+            permanent_failure_status: io.permanent_failure_status,
+            battery_data: io.battery_data.clone(),
+            charger_data: io.charger_data.clone(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, Default, Add, Deserialize, Serialize)]
@@ -111,6 +152,18 @@ impl Div<f32> for NormalizedData {
     }
 }
 
+impl NormalizedResource {
+    /// Running on battery, as opposed to charging or being held on the
+    /// adapter. A battery that doesn't say whether an adapter is attached
+    /// goes by the adapter being named.
+    pub fn is_discharging(&self) -> bool {
+        !self.is_charging
+            && !self
+                .external_connected
+                .unwrap_or(self.adapter_name.is_some())
+    }
+}
+
 impl Deref for NormalizedResource {
     type Target = NormalizedData;
 
@@ -148,6 +201,8 @@ impl From<&IORegistry> for NormalizedResource {
             max_capacity: io.apple_raw_max_capacity,
             design_capacity: io.design_capacity,
             current_capacity: io.apple_raw_current_capacity,
+            voltage: io.voltage as f32 / 1000.,
+            external_connected: io.external_connected,
             data: NormalizedData {
                 system_in,
                 system_load,
@@ -167,6 +222,8 @@ impl From<&IORegistry> for NormalizedResource {
                     / 1000.,
                 adapter_amperage: io.adapter_details.current.unwrap_or_default() as f32 / 1000.,
             },
+            thermal: None,
+            battery: BatteryDetails::from(io),
         }
     }
 }
@@ -193,6 +250,8 @@ impl From<(&IORegistry, &SMCPowerData)> for NormalizedResource {
             max_capacity: io.apple_raw_max_capacity,
             design_capacity: io.design_capacity,
             current_capacity: io.apple_raw_current_capacity,
+            voltage: io.voltage as f32 / 1000.,
+            external_connected: io.external_connected,
             data: NormalizedData {
                 system_in: smc.delivery_rate,
                 system_load: smc.system_total,
@@ -216,43 +275,36 @@ impl From<(&IORegistry, &SMCPowerData)> for NormalizedResource {
                     / 1000.,
                 adapter_amperage: io.adapter_details.current.unwrap_or_default() as f32 / 1000.,
             },
+            thermal: None,
+            battery: BatteryDetails::from(io),
         }
     }
 }
 
-pub fn get_mac_ioreg_dict() -> anyhow::Result<CFDictionary> {
-    let mut master_port: mach_port_t = 0;
-    if unsafe { IOMasterPort(0, &mut master_port) } != 0 {
-        bail!("could not get master port");
+impl From<&MergedPowerData> for NormalizedResource {
+    fn from(data: &MergedPowerData) -> Self {
+        let mut res = match &data.smc {
+            Some(smc) => Self::from((&data.ioreg, smc)),
+            None => Self::from(&data.ioreg),
+        };
+        res.is_local = data.from == PowerDataFrom::Local;
+        res.thermal = data.thermal.clone();
+        res
     }
-    let name = CString::new("AppleSmartBattery").unwrap();
-    let matching_dict = unsafe { IOServiceMatching(name.as_ptr()) };
-
-    let result = unsafe { IOServiceGetMatchingService(master_port, matching_dict) };
-
-    let mut properties: CFMutableDictionaryRef = unsafe { mem::zeroed() };
-    if unsafe { IORegistryEntryCreateCFProperties(result, &mut properties, kCFAllocatorDefault, 0) }
-        != kIOReturnSuccess
-    {
-        bail!("could not get properties");
-    }
-
-    unsafe { Ok(CFDictionary::wrap_under_create_rule(properties)) }
-}
-
-pub fn get_mac_ioreg() -> anyhow::Result<IORegistry> {
-    let dic = get_mac_ioreg_dict()?;
-    unsafe { mem::transmute(dict_into::<repr::IORegistry>(dic)) }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct MergedPowerData {
     pub from: PowerDataFrom,
     pub smc: Option<SMCPowerData>,
     pub ioreg: IORegistry,
+    #[serde(default)]
+    pub thermal: Option<ThermalData>,
 }
 
-#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub enum PowerDataFrom {
     #[default]
     Local,
@@ -326,3 +378,68 @@ impl PowerStatistic {
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_battery_details_out_of_the_serialized_resource() {
+        let io = IORegistry {
+            apple_raw_max_capacity: 4000,
+            serial: Some("F5D0000000000".into()),
+            external_connected: Some(true),
+            battery_data: Some(BatteryData {
+                cell_voltage: Some(vec![4100, 4110]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let res = NormalizedResource::from(&io);
+        assert_eq!(res.battery.serial.as_deref(), Some("F5D0000000000"));
+
+        let json = serde_json::to_value(&res).unwrap();
+        assert!(json.get("battery").is_none());
+        assert_eq!(json["externalConnected"], true);
+
+        let res: NormalizedResource = serde_json::from_value(json).unwrap();
+        assert_eq!(res.external_connected, Some(true));
+        assert!(res.battery.serial.is_none());
+    }
+
+    #[test]
+    fn tells_discharging_apart() {
+        let on_battery = NormalizedResource {
+            external_connected: Some(false),
+            ..Default::default()
+        };
+        assert!(on_battery.is_discharging());
+
+        let charging = NormalizedResource {
+            is_charging: true,
+            external_connected: Some(true),
+            ..on_battery.clone()
+        };
+        assert!(!charging.is_discharging());
+
+        // held on the adapter, e.g. at the charge limit
+        let held = NormalizedResource {
+            external_connected: Some(true),
+            ..on_battery.clone()
+        };
+        assert!(!held.is_discharging());
+
+        // a battery that doesn't say falls back to the adapter
+        let unknown = NormalizedResource {
+            external_connected: None,
+            ..on_battery.clone()
+        };
+        assert!(unknown.is_discharging());
+        let adapter = NormalizedResource {
+            external_connected: None,
+            adapter_name: Some("USB-C".to_string()),
+            ..on_battery
+        };
+        assert!(!adapter.is_discharging());
+    }
+}
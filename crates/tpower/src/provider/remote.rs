@@ -1,15 +1,19 @@
-use std::mem;
-
 use core_foundation::{base::TCFType, dictionary::CFDictionary};
 use thiserror::Error;
 
+use super::{MergedPowerData, PowerDataFrom, PowerSource, SourceCapabilities};
 use crate::{
     cfdic,
-    de::{repr, IORegistry},
-    ffi::wrapper::ServiceConnection,
+    de::{IORegistry, IORegistryParseError},
+    ffi::{
+        wrapper::{Device, DeviceError, ServiceConnection},
+        InterfaceType,
+    },
     util::{dict_into, DictParseError},
 };
 
+const DIAGNOSTICS_RELAY_SERVICE: &str = "com.apple.mobile.diagnostics_relay";
+
 #[derive(Debug, Error)]
 pub enum DeviceDataError {
     #[error("Failed to send message: {0}")]
@@ -18,9 +22,27 @@ pub enum DeviceDataError {
     Receive(i32),
     #[error("Failed to parse message: {0}")]
     Parse(#[from] DictParseError),
+    #[error("Failed to parse IORegistry: {0}")]
+    Registry(#[from] IORegistryParseError),
 }
 
 pub fn get_device_ioreg(conn: &ServiceConnection) -> Result<IORegistry, DeviceDataError> {
+    let response = dict_into::<plist::Value>(request_ioreg(conn)?)?;
+
+    Ok(IORegistry::from_value(&response)?)
+}
+
+/// Everything the device reports, including the keys [`IORegistry`] doesn't
+/// model.
+pub fn get_device_ioreg_raw(
+    conn: &ServiceConnection,
+) -> Result<plist::Dictionary, DeviceDataError> {
+    let response = dict_into::<plist::Value>(request_ioreg(conn)?)?;
+
+    Ok(IORegistry::entry(&response)?.clone())
+}
+
+fn request_ioreg(conn: &ServiceConnection) -> Result<CFDictionary, DeviceDataError> {
     unsafe {
         conn.send(
             cfdic! {
@@ -36,8 +58,56 @@ pub fn get_device_ioreg(conn: &ServiceConnection) -> Result<IORegistry, DeviceDa
         CFDictionary::wrap_under_create_rule(conn.receive().map_err(DeviceDataError::Receive)?)
     };
 
-    let data = dict_into::<repr::IORegistryDiagnostic>(response)?;
+    Ok(response)
+}
+
+/// An iOS device connected over USB or WiFi, read through `diagnostics_relay`.
+pub struct RemoteSource {
+    udid: String,
+    name: String,
+    interface_type: InterfaceType,
+    conn: ServiceConnection,
+}
+
+impl RemoteSource {
+    /// The device must be prepared with [`Device::prepare_device`] first.
+    pub fn new(device: &Device) -> Result<Self, DeviceError> {
+        Ok(Self {
+            udid: device.udid.clone(),
+            name: device.name()?,
+            interface_type: device.interface_type,
+            conn: device.start_service(DIAGNOSTICS_RELAY_SERVICE)?,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn ioreg_raw(&self) -> Result<plist::Dictionary, DeviceDataError> {
+        get_device_ioreg_raw(&self.conn)
+    }
+}
+
+impl PowerSource for RemoteSource {
+    fn identity(&self) -> PowerDataFrom {
+        PowerDataFrom::Remote((self.udid.clone(), self.name.clone(), self.interface_type))
+    }
+
+    fn capabilities(&self) -> SourceCapabilities {
+        SourceCapabilities {
+            smc: false,
+            power_telemetry: true,
+            adapter_details: true,
+        }
+    }
 
-    // SAFETY: IORegistry and repr::IORegistry are disigned to be the same
-    unsafe { mem::transmute(data.diagnostics.ioregistry) }
+    fn sample(&mut self) -> anyhow::Result<MergedPowerData> {
+        Ok(MergedPowerData {
+            from: self.identity(),
+            smc: None,
+            thermal: None,
+            ioreg: get_device_ioreg(&self.conn)?,
+        })
+    }
 }
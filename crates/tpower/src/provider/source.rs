@@ -0,0 +1,185 @@
+use serde::{Deserialize, Serialize};
+
+use super::{MergedPowerData, NormalizedResource, PowerDataFrom};
+
+/// What a [`PowerSource`] is able to report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[serde(rename_all = "camelCase")]
+pub struct SourceCapabilities {
+    /// SMC sensors are available, e.g. screen and heatpipe power.
+    pub smc: bool,
+    /// `PowerTelemetryData` is reported along with the IORegistry.
+    pub power_telemetry: bool,
+    /// Adapter details are reported along with the IORegistry.
+    pub adapter_details: bool,
+}
+
+/// A backend producing power samples, e.g. the local Mac or a connected
+/// iOS device.
+pub trait PowerSource {
+    /// Where the samples of this source come from.
+    fn identity(&self) -> PowerDataFrom;
+
+    fn capabilities(&self) -> SourceCapabilities;
+
+    /// Take a single raw sample.
+    fn sample(&mut self) -> anyhow::Result<MergedPowerData>;
+
+    /// Take a single sample and convert it to a [`NormalizedResource`].
+    fn sample_normalized(&mut self) -> anyhow::Result<NormalizedResource> {
+        self.sample().map(|data| NormalizedResource::from(&data))
+    }
+}
+
+impl<S: PowerSource + ?Sized> PowerSource for Box<S> {
+    fn identity(&self) -> PowerDataFrom {
+        (**self).identity()
+    }
+
+    fn capabilities(&self) -> SourceCapabilities {
+        (**self).capabilities()
+    }
+
+    fn sample(&mut self) -> anyhow::Result<MergedPowerData> {
+        (**self).sample()
+    }
+
+    fn sample_normalized(&mut self) -> anyhow::Result<NormalizedResource> {
+        (**self).sample_normalized()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use anyhow::anyhow;
+
+    use super::*;
+    use crate::{
+        de::{IORegistry, PowerTelemetryData},
+        ffi::{smc::SMCPowerData, InterfaceType},
+    };
+
+    /// Replays canned samples, then fails.
+    struct FakeSource {
+        from: PowerDataFrom,
+        samples: VecDeque<MergedPowerData>,
+    }
+
+    impl PowerSource for FakeSource {
+        fn identity(&self) -> PowerDataFrom {
+            self.from.clone()
+        }
+
+        fn capabilities(&self) -> SourceCapabilities {
+            SourceCapabilities {
+                smc: self.from == PowerDataFrom::Local,
+                power_telemetry: true,
+                adapter_details: true,
+            }
+        }
+
+        fn sample(&mut self) -> anyhow::Result<MergedPowerData> {
+            self.samples
+                .pop_front()
+                .ok_or_else(|| anyhow!("no more samples"))
+        }
+    }
+
+    fn ioreg() -> IORegistry {
+        IORegistry {
+            power_telemetry_data: Some(PowerTelemetryData {
+                adapter_efficiency_loss: 500,
+                battery_power: 3000,
+                system_load: 8000,
+                system_power_in: 11000,
+                ..Default::default()
+            }),
+            voltage: 12500,
+            apple_raw_current_capacity: 2000,
+            apple_raw_max_capacity: 4000,
+            current_capacity: 51,
+            is_charging: true,
+            temperature: 3050,
+            time_remaining: 90,
+            ..Default::default()
+        }
+    }
+
+    fn source(from: PowerDataFrom, smc: Option<SMCPowerData>) -> FakeSource {
+        FakeSource {
+            samples: VecDeque::from([MergedPowerData {
+                from: from.clone(),
+                smc,
+                ioreg: ioreg(),
+                thermal: None,
+            }]),
+            from,
+        }
+    }
+
+    #[test]
+    fn normalizes_remote_samples_from_the_ioregistry() {
+        let from = PowerDataFrom::Remote(("udid".into(), "iPhone".into(), InterfaceType::USB));
+        let mut source = source(from, None);
+        let res = source.sample_normalized().unwrap();
+
+        assert!(!res.is_local);
+        assert!(res.is_charging);
+        assert_eq!(res.time_remain.as_secs(), 90 * 60);
+        assert_eq!(res.system_in, 11.);
+        assert_eq!(res.system_load, 8.);
+        assert_eq!(res.battery_power, 3.);
+        assert_eq!(res.adapter_power, 11.5);
+        assert_eq!(res.efficiency_loss, 0.5);
+        assert_eq!(res.battery_level, 51);
+        assert_eq!(res.absolute_battery_level, 50.);
+        assert_eq!(res.temperature, 30.5);
+        assert_eq!(res.voltage, 12.5);
+
+        assert!(source.sample_normalized().is_err());
+    }
+
+    #[test]
+    fn prefers_smc_readings_on_the_local_machine() {
+        let smc = SMCPowerData {
+            battery_rate: 2.,
+            delivery_rate: 20.,
+            system_total: 15.,
+            brightness: 1.5,
+            heatpipe: 4.,
+            charging_status: 1.,
+            time_to_full: 30.,
+            temperature: 31.,
+            ..Default::default()
+        };
+        let mut source = source(PowerDataFrom::Local, Some(smc));
+        let res = source.sample_normalized().unwrap();
+
+        assert!(res.is_local);
+        assert!(res.is_charging);
+        assert_eq!(res.time_remain.as_secs(), 30 * 60);
+        assert_eq!(res.system_in, 20.);
+        assert_eq!(res.system_load, 15.);
+        // what the SMC reports is less than what goes unaccounted for
+        assert_eq!(res.battery_power, 5.);
+        assert_eq!(res.adapter_power, 20.5);
+        assert_eq!(res.brightness_power, 1.5);
+        assert_eq!(res.heatpipe_power, 4.);
+        assert_eq!(res.temperature, 31.);
+    }
+
+    #[test]
+    fn boxed_sources_delegate() {
+        let mut source: Box<dyn PowerSource> = Box::new(source(PowerDataFrom::Local, None));
+
+        assert_eq!(source.identity(), PowerDataFrom::Local);
+        assert!(source.capabilities().smc);
+        let res = source.sample_normalized().unwrap();
+        assert!(res.is_local);
+        assert_eq!(res.system_load, 8.);
+        assert!(source.sample().is_err());
+    }
+}
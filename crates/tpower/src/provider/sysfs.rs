@@ -0,0 +1,489 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context};
+
+use super::{MergedPowerData, PowerDataFrom, PowerSource, SourceCapabilities};
+use crate::de::{AdapterDetails, IORegistry, PowerTelemetryData};
+
+pub const DEFAULT_SYSFS_ROOT: &str = "/sys/class/power_supply";
+
+/// A Linux machine, read from the `power_supply` class in sysfs.
+///
+/// The values are converted to the units `AppleSmartBattery` reports
+/// (mV, mA, mAh, mW, 0.01 °C), so the samples go through the same
+/// conversion as the macOS ones.
+#[derive(Debug, Clone)]
+pub struct SysfsSource {
+    root: PathBuf,
+    /// mW the system drew the last time it could be measured, stands in
+    /// when the adapter doesn't report its input
+    last_load: Option<i64>,
+}
+
+impl Default for SysfsSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SysfsSource {
+    pub fn new() -> Self {
+        Self::with_root(DEFAULT_SYSFS_ROOT)
+    }
+
+    /// Read from `root` instead of `/sys/class/power_supply`, e.g. a fixture
+    /// directory tree.
+    pub fn with_root(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            last_load: None,
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn supplies(&self) -> anyhow::Result<Vec<Supply>> {
+        let mut supplies = fs::read_dir(&self.root)
+            .with_context(|| format!("could not read {}", self.root.display()))?
+            .filter_map(Result::ok)
+            .map(|entry| Supply {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                path: entry.path(),
+            })
+            .collect::<Vec<_>>();
+        supplies.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(supplies)
+    }
+
+    pub fn read_ioreg(&self) -> anyhow::Result<IORegistry> {
+        self.read(self.last_load).map(|(io, _)| io)
+    }
+
+    /// The sample and the load measured along with it, if any.
+    fn read(&self, load_estimate: Option<i64>) -> anyhow::Result<(IORegistry, Option<i64>)> {
+        let supplies = self.supplies()?;
+
+        let Some(battery) = supplies
+            .iter()
+            // peripherals such as mice report `scope` = Device
+            .find(|s| {
+                s.kind() == SupplyKind::Battery && s.read("scope").as_deref() != Some("Device")
+            })
+        else {
+            bail!("no system battery found in {}", self.root.display());
+        };
+
+        let adapters = supplies
+            .iter()
+            .filter(|s| matches!(s.kind(), SupplyKind::Mains | SupplyKind::Usb))
+            .filter(|s| s.read_i64("online") == Some(1))
+            .collect::<Vec<_>>();
+
+        Ok(battery_to_ioreg(battery, &adapters, load_estimate))
+    }
+}
+
+impl PowerSource for SysfsSource {
+    fn identity(&self) -> PowerDataFrom {
+        PowerDataFrom::Local
+    }
+
+    fn capabilities(&self) -> SourceCapabilities {
+        SourceCapabilities {
+            smc: false,
+            power_telemetry: true,
+            adapter_details: true,
+        }
+    }
+
+    fn sample(&mut self) -> anyhow::Result<MergedPowerData> {
+        let (ioreg, load) = self.read(self.last_load)?;
+        if load.is_some() {
+            self.last_load = load;
+        }
+        Ok(MergedPowerData {
+            from: PowerDataFrom::Local,
+            smc: None,
+            thermal: None,
+            ioreg,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SupplyKind {
+    Battery,
+    Mains,
+    Usb,
+    Other,
+}
+
+#[derive(Debug)]
+struct Supply {
+    name: String,
+    path: PathBuf,
+}
+
+impl Supply {
+    fn read(&self, attr: &str) -> Option<String> {
+        fs::read_to_string(self.path.join(attr))
+            .ok()
+            .map(|s| s.trim().to_string())
+    }
+
+    fn read_i64(&self, attr: &str) -> Option<i64> {
+        self.read(attr)?.parse().ok()
+    }
+
+    fn kind(&self) -> SupplyKind {
+        match self.read("type").as_deref() {
+            Some("Battery") => SupplyKind::Battery,
+            Some("Mains") => SupplyKind::Mains,
+            Some(t) if t.starts_with("USB") => SupplyKind::Usb,
+            // some drivers omit `type`, fall back to the conventional names
+            None if self.name.starts_with("BAT") => SupplyKind::Battery,
+            None if self.name.starts_with("AC") || self.name.starts_with("ADP") => {
+                SupplyKind::Mains
+            }
+            _ => SupplyKind::Other,
+        }
+    }
+}
+
+/// sysfs reports µV, µA, µW, µAh and µWh
+fn micro_to_milli(v: i64) -> i32 {
+    (v / 1000) as i32
+}
+
+fn battery_to_ioreg(
+    battery: &Supply,
+    adapters: &[&Supply],
+    load_estimate: Option<i64>,
+) -> (IORegistry, Option<i64>) {
+    let status = battery.read("status").unwrap_or_default();
+    let is_charging = status == "Charging";
+    let fully_charged = status == "Full";
+
+    let voltage = battery.read_i64("voltage_now").map_or(0, micro_to_milli);
+    let design_voltage = battery
+        .read_i64("voltage_min_design")
+        .map_or(voltage, micro_to_milli);
+
+    // drivers disagree on the sign of `current_now`, derive it from `status`:
+    // unless it's charging, whatever flows comes out of the battery, e.g. when
+    // "Not charging" on an adapter too weak for the load
+    let sign = if is_charging { 1 } else { -1 };
+    let amperage = battery
+        .read_i64("current_now")
+        .map_or(0, |v| sign * micro_to_milli(v.abs()));
+    // like `PowerTelemetryData`, a magnitude in either direction
+    let battery_power = battery.read_i64("power_now").map_or_else(
+        || i64::from(amperage.abs()) * i64::from(voltage) / 1000,
+        |v| v.abs() / 1000,
+    );
+
+    // batteries report either charge_* (µAh) or energy_* (µWh)
+    let capacity = |name: &str| {
+        battery
+            .read_i64(&format!("charge_{name}"))
+            .map(micro_to_milli)
+            .or_else(|| {
+                let energy = battery.read_i64(&format!("energy_{name}"))?;
+                (design_voltage > 0).then(|| (energy / i64::from(design_voltage)) as i32)
+            })
+            .unwrap_or_default()
+    };
+    let current_capacity = capacity("now");
+    let full_capacity = capacity("full");
+    let design_capacity = capacity("full_design");
+
+    let level = battery.read_i64("capacity").map_or_else(
+        || {
+            if full_capacity > 0 {
+                current_capacity * 100 / full_capacity
+            } else {
+                0
+            }
+        },
+        |v| v as i32,
+    );
+
+    let time_remaining = battery
+        .read_i64(if is_charging {
+            "time_to_full_now"
+        } else {
+            "time_to_empty_now"
+        })
+        .map_or(0, |secs| (secs / 60) as i32);
+
+    let adapter_details = adapters
+        .iter()
+        .find(|a| a.kind() == SupplyKind::Usb && a.read_i64("voltage_now").is_some())
+        .or_else(|| adapters.first())
+        .map(|a| adapter_details(a))
+        .unwrap_or_default();
+
+    // what the PD source negotiated is the best guess of the input sysfs offers
+    let adapter_power = adapter_details
+        .adapter_voltage
+        .zip(adapter_details.current)
+        .map_or(0, |(v, c)| i64::from(v) * i64::from(c) / 1000);
+    let into_battery = if is_charging {
+        battery_power
+    } else {
+        -battery_power
+    };
+    let (system_power_in, system_load, measured_load) = if adapters.is_empty() {
+        (0, battery_power, Some(battery_power))
+    } else if adapter_power > 0 {
+        let load = (adapter_power - into_battery).max(0);
+        (adapter_power, load, Some(load))
+    } else {
+        // the adapter doesn't tell, assume the load hasn't changed since it
+        // was last measured, the input covers it and what the battery takes
+        let load = load_estimate.unwrap_or_default().max(-into_battery);
+        ((load + into_battery).max(0), load, None)
+    };
+    let system_power_in = system_power_in as i32;
+
+    let io = IORegistry {
+        adapter_details,
+        power_telemetry_data: Some(PowerTelemetryData {
+            adapter_efficiency_loss: 0,
+            battery_power,
+            system_current_in: if voltage > 0 {
+                system_power_in * 1000 / voltage
+            } else {
+                0
+            },
+            system_energy_consumed: 0,
+            system_load,
+            system_power_in,
+            system_voltage_in: voltage,
+        }),
+        absolute_capacity: current_capacity,
+        amperage,
+        voltage,
+        apple_raw_battery_voltage: Some(voltage),
+        apple_raw_current_capacity: current_capacity,
+        apple_raw_max_capacity: full_capacity,
+        current_capacity: level,
+        cycle_count: battery.read_i64("cycle_count").unwrap_or_default() as i32,
+        design_capacity,
+        fully_charged,
+        instant_amperage: amperage,
+        is_charging,
+        max_capacity: 100,
+        // sysfs reports 0.1 °C, IORegistry 0.01 °C
+        temperature: battery.read_i64("temp").map_or(0, |t| t as i32 * 10),
+        time_remaining,
+        update_time: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64),
+        battery_data: None,
+        charger_data: None,
+        serial: battery.read("serial_number").filter(|s| !s.is_empty()),
+        manufacture_date: None,
+        battery_installed: battery.read_i64("present").map(|v| v != 0),
+        external_connected: Some(!adapters.is_empty()),
+        permanent_failure_status: None,
+        extra: BTreeMap::new(),
+    };
+    (io, measured_load)
+}
+
+fn adapter_details(adapter: &Supply) -> AdapterDetails {
+    let voltage = adapter
+        .read_i64("voltage_now")
+        .or_else(|| adapter.read_i64("voltage_max"))
+        .map(micro_to_milli);
+    let current = adapter
+        .read_i64("current_max")
+        .or_else(|| adapter.read_i64("current_now"))
+        .map(micro_to_milli);
+
+    AdapterDetails {
+        adapter_voltage: voltage,
+        is_wireless: Some(false),
+        watts: voltage
+            .zip(current)
+            .map(|(v, c)| (i64::from(v) * i64::from(c) / 1_000_000) as i32),
+        name: adapter
+            .read("model_name")
+            .or_else(|| Some(adapter.name.clone())),
+        current,
+        description: adapter.read("usb_type").or_else(|| adapter.read("type")),
+        extra: BTreeMap::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// A `power_supply` tree of `(supply, [(attribute, value)])`.
+    fn fixture(supplies: &[(&str, &[(&str, &str)])]) -> TempDir {
+        let root = tempfile::tempdir().unwrap();
+        for (name, attrs) in supplies {
+            let dir = root.path().join(name);
+            fs::create_dir(&dir).unwrap();
+            for (attr, value) in *attrs {
+                fs::write(dir.join(attr), format!("{value}\n")).unwrap();
+            }
+        }
+        root
+    }
+
+    const BATTERY: &[(&str, &str)] = &[
+        ("type", "Battery"),
+        ("present", "1"),
+        ("voltage_now", "12000000"),
+        ("current_now", "-1500000"),
+        ("charge_now", "2000000"),
+        ("charge_full", "4000000"),
+        ("charge_full_design", "4500000"),
+        ("capacity", "50"),
+        ("cycle_count", "42"),
+        ("temp", "305"),
+        ("serial_number", "1234"),
+    ];
+
+    fn battery(status: &str) -> Vec<(&str, &str)> {
+        let mut attrs = BATTERY.to_vec();
+        attrs.push(("status", status));
+        attrs
+    }
+
+    fn sample(root: &TempDir) -> IORegistry {
+        SysfsSource::with_root(root.path()).sample().unwrap().ioreg
+    }
+
+    #[test]
+    fn reports_discharge_as_a_magnitude() {
+        let root = fixture(&[("BAT0", &battery("Discharging"))]);
+        let io = sample(&root);
+        let ptd = io.ptd().unwrap();
+
+        assert!(!io.is_charging);
+        assert_eq!(io.amperage, -1500);
+        assert_eq!(io.voltage, 12000);
+        assert_eq!(ptd.battery_power, 18000);
+        assert_eq!(ptd.system_power_in, 0);
+        assert_eq!(ptd.system_load, 18000);
+        assert_eq!(io.apple_raw_current_capacity, 2000);
+        assert_eq!(io.apple_raw_max_capacity, 4000);
+        assert_eq!(io.design_capacity, 4500);
+        assert_eq!(io.current_capacity, 50);
+        assert_eq!(io.cycle_count, 42);
+        assert_eq!(io.temperature, 3050);
+        assert_eq!(io.serial.as_deref(), Some("1234"));
+        assert_eq!(io.external_connected, Some(false));
+    }
+
+    #[test]
+    fn derives_the_load_from_the_adapter_input() {
+        let usb: &[(&str, &str)] = &[
+            ("type", "USB"),
+            ("online", "1"),
+            ("voltage_now", "20000000"),
+            ("current_max", "3000000"),
+        ];
+        let root = fixture(&[("BAT0", &battery("Charging")), ("ucsi-source-psy", usb)]);
+        let io = sample(&root);
+        let ptd = io.ptd().unwrap();
+
+        assert!(io.is_charging);
+        assert_eq!(io.amperage, 1500);
+        assert_eq!(io.adapter_details.watts, Some(60));
+        assert_eq!(ptd.battery_power, 18000);
+        assert_eq!(ptd.system_power_in, 60000);
+        assert_eq!(ptd.system_load, 42000);
+        assert_eq!(io.external_connected, Some(true));
+    }
+
+    #[test]
+    fn not_charging_draws_from_the_battery() {
+        let usb: &[(&str, &str)] = &[
+            ("type", "USB"),
+            ("online", "1"),
+            ("voltage_now", "5000000"),
+            ("current_max", "3000000"),
+        ];
+        let root = fixture(&[("BAT0", &battery("Not charging")), ("usb", usb)]);
+        let io = sample(&root);
+        let ptd = io.ptd().unwrap();
+
+        assert!(!io.is_charging);
+        assert_eq!(io.amperage, -1500);
+        assert_eq!(ptd.battery_power, 18000);
+        assert_eq!(ptd.system_power_in, 15000);
+        assert_eq!(ptd.system_load, 33000);
+    }
+
+    #[test]
+    fn estimates_the_input_of_a_silent_adapter() {
+        let root = fixture(&[
+            ("AC", &[("online", "0")]),
+            ("BAT0", &battery("Discharging")),
+        ]);
+        let mut source = SysfsSource::with_root(root.path());
+        assert_eq!(source.sample().unwrap().ptd().unwrap().system_load, 18000);
+
+        // plugged in, the mains supply reports nothing but `online`
+        fs::write(root.path().join("AC/online"), "1\n").unwrap();
+        fs::write(root.path().join("BAT0/status"), "Charging\n").unwrap();
+        fs::write(root.path().join("BAT0/current_now"), "500000\n").unwrap();
+        let io = source.sample().unwrap().ioreg;
+        let ptd = io.ptd().unwrap();
+
+        assert!(io.is_charging);
+        assert_eq!(ptd.battery_power, 6000);
+        assert_eq!(ptd.system_load, 18000);
+        assert_eq!(ptd.system_power_in, 24000);
+        assert_eq!(io.external_connected, Some(true));
+
+        // without a measurement, at least what goes into the battery comes in
+        let io = SysfsSource::with_root(root.path()).read_ioreg().unwrap();
+        assert_eq!(io.ptd().unwrap().system_power_in, 6000);
+    }
+
+    #[test]
+    fn reads_energy_and_power_attributes() {
+        let root = fixture(&[
+            ("ADP1", &[("online", "0")]),
+            ("BAT1", &[
+                ("status", "Discharging"),
+                ("voltage_now", "11000000"),
+                ("voltage_min_design", "10000000"),
+                ("power_now", "9000000"),
+                ("energy_now", "20000000"),
+                ("energy_full", "40000000"),
+                ("energy_full_design", "50000000"),
+            ]),
+        ]);
+        let io = sample(&root);
+
+        assert_eq!(io.ptd().unwrap().battery_power, 9000);
+        assert_eq!(io.apple_raw_current_capacity, 2000);
+        assert_eq!(io.apple_raw_max_capacity, 4000);
+        assert_eq!(io.design_capacity, 5000);
+        assert_eq!(io.current_capacity, 50);
+    }
+
+    #[test]
+    fn skips_peripheral_batteries() {
+        let mouse: &[(&str, &str)] = &[("type", "Battery"), ("scope", "Device")];
+        let root = fixture(&[("hidpp_battery_0", mouse)]);
+
+        assert!(SysfsSource::with_root(root.path()).read_ioreg().is_err());
+    }
+}
@@ -0,0 +1,381 @@
+//! Record raw power samples to a trace file and play them back.
+//!
+//! A trace starts with [`TRACE_MAGIC`] and the format version as a
+//! little-endian `u16`, followed by a gzip stream of newline-delimited JSON
+//! [`TraceFrame`]s. Frames are flushed as they are written, so a trace cut
+//! short by a crash is still readable up to the last complete frame.
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    path::Path,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::bail;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::provider::{MergedPowerData, PowerDataFrom, PowerSource, SourceCapabilities};
+
+pub const TRACE_MAGIC: &[u8; 8] = b"TPTRACE\0";
+pub const TRACE_VERSION: u16 = 1;
+
+#[derive(Debug, Error)]
+pub enum TraceError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("not a tpower trace")]
+    BadMagic,
+
+    #[error("unsupported trace version {0}, expected {TRACE_VERSION}")]
+    UnsupportedVersion(u16),
+
+    #[error("malformed frame: {0}")]
+    Frame(#[from] serde_json::Error),
+}
+
+/// A single tick of a source as it was sampled.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceFrame {
+    /// Milliseconds since the unix epoch.
+    pub timestamp: u64,
+    pub data: MergedPowerData,
+}
+
+impl TraceFrame {
+    pub fn now(data: MergedPowerData) -> Self {
+        Self {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis() as u64),
+            data,
+        }
+    }
+}
+
+pub struct TraceWriter<W: Write> {
+    encoder: GzEncoder<W>,
+}
+
+impl TraceWriter<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, TraceError> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(mut inner: W) -> Result<Self, TraceError> {
+        inner.write_all(TRACE_MAGIC)?;
+        inner.write_all(&TRACE_VERSION.to_le_bytes())?;
+
+        Ok(Self {
+            encoder: GzEncoder::new(inner, Compression::default()),
+        })
+    }
+
+    pub fn write_frame(&mut self, frame: &TraceFrame) -> Result<(), TraceError> {
+        serde_json::to_writer(&mut self.encoder, frame)?;
+        self.encoder.write_all(b"\n")?;
+        self.encoder.flush()?;
+        Ok(())
+    }
+
+    /// Write `data` as sampled just now.
+    pub fn record(&mut self, data: &MergedPowerData) -> Result<(), TraceError> {
+        self.write_frame(&TraceFrame::now(data.clone()))
+    }
+
+    /// Write the gzip trailer and return the underlying writer.
+    pub fn finish(self) -> Result<W, TraceError> {
+        Ok(self.encoder.finish()?)
+    }
+}
+
+pub struct TraceReader<R: Read> {
+    lines: io::Lines<BufReader<GzDecoder<R>>>,
+}
+
+impl TraceReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, TraceError> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> TraceReader<R> {
+    pub fn new(mut inner: R) -> Result<Self, TraceError> {
+        let mut magic = [0u8; 8];
+        inner.read_exact(&mut magic)?;
+        if &magic != TRACE_MAGIC {
+            return Err(TraceError::BadMagic);
+        }
+
+        let mut version = [0u8; 2];
+        inner.read_exact(&mut version)?;
+        let version = u16::from_le_bytes(version);
+        if version != TRACE_VERSION {
+            return Err(TraceError::UnsupportedVersion(version));
+        }
+
+        Ok(Self {
+            lines: BufReader::new(GzDecoder::new(inner)).lines(),
+        })
+    }
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = Result<TraceFrame, TraceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.lines.next()? {
+            Ok(line) if line.trim().is_empty() => self.next(),
+            Ok(line) => Some(serde_json::from_str(&line).map_err(Into::into)),
+            // the trace was not finished, e.g. the recording process crashed
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => None,
+            Err(err) => Some(Err(err.into())),
+        }
+    }
+}
+
+/// Wraps a source and writes every sample it takes to a trace.
+pub struct RecordingSource<S, W: Write> {
+    source: S,
+    writer: TraceWriter<W>,
+}
+
+impl<S: PowerSource, W: Write> RecordingSource<S, W> {
+    pub fn new(source: S, writer: TraceWriter<W>) -> Self {
+        Self { source, writer }
+    }
+
+    pub fn into_inner(self) -> (S, TraceWriter<W>) {
+        (self.source, self.writer)
+    }
+}
+
+impl<S: PowerSource, W: Write> PowerSource for RecordingSource<S, W> {
+    fn identity(&self) -> PowerDataFrom {
+        self.source.identity()
+    }
+
+    fn capabilities(&self) -> SourceCapabilities {
+        self.source.capabilities()
+    }
+
+    fn sample(&mut self) -> anyhow::Result<MergedPowerData> {
+        let data = self.source.sample()?;
+        self.writer.record(&data)?;
+        Ok(data)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ReplaySpeed {
+    /// Wait between frames as long as the recording did.
+    #[default]
+    Recorded,
+    /// Wait between frames as long as the recording did, divided by the factor.
+    Accelerated(f32),
+    /// Never wait.
+    Unthrottled,
+}
+
+/// Plays a trace back as a [`PowerSource`].
+///
+/// [`PowerSource::sample`] blocks the current thread until the next frame
+/// is due and fails once the trace is exhausted.
+pub struct ReplaySource {
+    frames: Vec<TraceFrame>,
+    /// Timestamp the replay clock starts from, kept when frames are filtered
+    /// so several replays of the same trace stay in step.
+    origin: u64,
+    position: usize,
+    speed: ReplaySpeed,
+    started: Option<Instant>,
+}
+
+impl ReplaySource {
+    pub fn open(path: impl AsRef<Path>, speed: ReplaySpeed) -> Result<Self, TraceError> {
+        Ok(Self::new(
+            TraceReader::open(path)?.collect::<Result<_, _>>()?,
+            speed,
+        ))
+    }
+
+    pub fn new(frames: Vec<TraceFrame>, speed: ReplaySpeed) -> Self {
+        Self {
+            origin: frames.first().map_or(0, |f| f.timestamp),
+            frames,
+            position: 0,
+            speed,
+            started: None,
+        }
+    }
+
+    /// Only replay the frames sampled from `from`.
+    #[must_use]
+    pub fn only(mut self, from: &PowerDataFrom) -> Self {
+        self.frames.retain(|f| &f.data.from == from);
+        self
+    }
+
+    pub fn frames(&self) -> &[TraceFrame] {
+        &self.frames
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.frames.len()
+    }
+
+    fn wait_for(&mut self, frame: usize) {
+        let offset =
+            Duration::from_millis(self.frames[frame].timestamp.saturating_sub(self.origin));
+        let offset = match self.speed {
+            ReplaySpeed::Recorded => offset,
+            ReplaySpeed::Accelerated(factor) if factor > 0. => offset.div_f32(factor),
+            ReplaySpeed::Accelerated(_) | ReplaySpeed::Unthrottled => return,
+        };
+
+        let started = *self.started.get_or_insert_with(Instant::now);
+        if let Some(remaining) = (started + offset).checked_duration_since(Instant::now()) {
+            thread::sleep(remaining);
+        }
+    }
+}
+
+impl PowerSource for ReplaySource {
+    fn identity(&self) -> PowerDataFrom {
+        self.frames
+            .first()
+            .map(|f| f.data.from.clone())
+            .unwrap_or_default()
+    }
+
+    fn capabilities(&self) -> SourceCapabilities {
+        SourceCapabilities {
+            smc: self.frames.iter().any(|f| f.data.smc.is_some()),
+            power_telemetry: self.frames.iter().any(|f| f.data.ptd().is_some()),
+            adapter_details: true,
+        }
+    }
+
+    fn sample(&mut self) -> anyhow::Result<MergedPowerData> {
+        if self.is_finished() {
+            bail!("end of trace");
+        }
+
+        self.wait_for(self.position);
+        self.position += 1;
+
+        Ok(self.frames[self.position - 1].data.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{de::IORegistry, ffi::InterfaceType};
+
+    fn frame(timestamp: u64, cycle_count: i32) -> TraceFrame {
+        TraceFrame {
+            timestamp,
+            data: MergedPowerData {
+                from: PowerDataFrom::Local,
+                smc: None,
+                ioreg: IORegistry {
+                    cycle_count,
+                    ..Default::default()
+                },
+                thermal: None,
+            },
+        }
+    }
+
+    fn record(frames: &[TraceFrame]) -> TraceWriter<Vec<u8>> {
+        let mut writer = TraceWriter::new(Vec::new()).unwrap();
+        for frame in frames {
+            writer.write_frame(frame).unwrap();
+        }
+        writer
+    }
+
+    fn cycle_counts(trace: &[u8]) -> Vec<i32> {
+        TraceReader::new(trace)
+            .unwrap()
+            .map(|frame| frame.unwrap().data.ioreg.cycle_count)
+            .collect()
+    }
+
+    #[test]
+    fn reads_back_what_was_written() {
+        let trace = record(&[frame(1000, 1), frame(2000, 2), frame(3000, 3)])
+            .finish()
+            .unwrap();
+
+        assert!(trace.starts_with(TRACE_MAGIC));
+        let frames = TraceReader::new(trace.as_slice())
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(frames.iter().map(|f| f.timestamp).collect::<Vec<_>>(), [
+            1000, 2000, 3000
+        ]);
+        assert_eq!(cycle_counts(&trace), [1, 2, 3]);
+    }
+
+    #[test]
+    fn reads_a_truncated_trace_up_to_the_last_complete_frame() {
+        let mut writer = record(&[frame(1000, 1), frame(2000, 2)]);
+        let complete = writer.encoder.get_ref().len();
+        writer.write_frame(&frame(3000, 3)).unwrap();
+        let unfinished = writer.encoder.get_ref().clone();
+
+        // never finished, e.g. the recording process crashed
+        assert_eq!(cycle_counts(&unfinished), [1, 2, 3]);
+        // and cut short in the middle of the last frame
+        let cut = (complete + unfinished.len()) / 2;
+        assert_eq!(cycle_counts(&unfinished[..cut]), [1, 2]);
+    }
+
+    #[test]
+    fn refuses_other_files() {
+        assert!(matches!(
+            TraceReader::new(&b"PK\x03\x04 not a trace"[..]),
+            Err(TraceError::BadMagic)
+        ));
+
+        let mut trace = TRACE_MAGIC.to_vec();
+        trace.extend_from_slice(&2u16.to_le_bytes());
+        assert!(matches!(
+            TraceReader::new(trace.as_slice()),
+            Err(TraceError::UnsupportedVersion(2))
+        ));
+
+        let mut writer = TraceWriter::new(Vec::new()).unwrap();
+        writer.encoder.write_all(b"{\"timestamp\":\n").unwrap();
+        let trace = writer.finish().unwrap();
+        let mut reader = TraceReader::new(trace.as_slice()).unwrap();
+        assert!(matches!(reader.next(), Some(Err(TraceError::Frame(_)))));
+    }
+
+    #[test]
+    fn replays_filtered_frames_unthrottled() {
+        let remote = PowerDataFrom::Remote(("udid".into(), "iPhone".into(), InterfaceType::USB));
+        let mut frames = vec![frame(1000, 1), frame(2000, 2), frame(3000, 3)];
+        frames[1].data.from = remote.clone();
+
+        let mut replay = ReplaySource::new(frames.clone(), ReplaySpeed::Unthrottled);
+        assert_eq!(replay.identity(), PowerDataFrom::Local);
+        assert_eq!(replay.sample().unwrap().cycle_count, 1);
+
+        let mut replay = ReplaySource::new(frames, ReplaySpeed::Unthrottled).only(&remote);
+        assert_eq!(replay.identity(), remote);
+        assert_eq!(replay.sample().unwrap().cycle_count, 2);
+        assert!(replay.is_finished());
+        assert!(replay.sample().is_err());
+    }
+}
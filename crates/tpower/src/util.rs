@@ -1,9 +1,11 @@
+#[cfg(apple_ffi)]
 use core_foundation::{
     base::{kCFAllocatorDefault, TCFType},
     data::CFData,
     dictionary::CFDictionary,
     propertylist::{CFPropertyListCreateXMLData, CFPropertyListSubClass},
 };
+#[cfg(apple_ffi)]
 use serde::de::DeserializeOwned;
 use thiserror::Error;
 
@@ -32,6 +34,7 @@ pub enum DictParseError {
     Deserialize(#[from] plist::Error),
 }
 
+#[cfg(apple_ffi)]
 pub fn dict_into<T: DeserializeOwned>(data: CFDictionary) -> Result<T, DictParseError> {
     let data = unsafe {
         CFPropertyListCreateXMLData(kCFAllocatorDefault, data.to_CFPropertyList().as_CFTypeRef())
@@ -55,3 +58,15 @@ pub fn get_mac_name() -> Option<String> {
 
     Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
 }
+
+/// The hardware model identifier, e.g. `Mac14,7`.
+pub fn get_hw_model() -> Option<String> {
+    let output = std::process::Command::new("sysctl")
+        .arg("-n")
+        .arg("hw.model")
+        .output()
+        .ok()?;
+
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .filter(|model| !model.is_empty())
+}
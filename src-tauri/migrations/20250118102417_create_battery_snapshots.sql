@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS battery_snapshots (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    udid TEXT NOT NULL,
+    name TEXT NOT NULL DEFAULT '',
+    is_remote INTEGER NOT NULL DEFAULT 0,
+    day TEXT NOT NULL,
+    max_capacity INTEGER NOT NULL,
+    design_capacity INTEGER NOT NULL,
+    cycle_count INTEGER NOT NULL,
+    min_temperature REAL NOT NULL,
+    max_temperature REAL NOT NULL,
+    adapter_name TEXT,
+    timestamp INTEGER NOT NULL,
+    UNIQUE (udid, day)
+);
\ No newline at end of file
@@ -0,0 +1,30 @@
+CREATE TABLE IF NOT EXISTS power_samples (
+    udid TEXT NOT NULL,
+    timestamp INTEGER NOT NULL,
+    system_load REAL NOT NULL,
+    system_in REAL NOT NULL,
+    battery_power REAL NOT NULL,
+    battery_level INTEGER NOT NULL,
+    is_charging INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS power_samples_udid_timestamp ON power_samples (udid, timestamp);
+
+CREATE TABLE IF NOT EXISTS power_rollups (
+    udid TEXT NOT NULL,
+    resolution INTEGER NOT NULL,
+    bucket INTEGER NOT NULL,
+    samples INTEGER NOT NULL,
+    system_load_min REAL NOT NULL,
+    system_load_avg REAL NOT NULL,
+    system_load_max REAL NOT NULL,
+    system_in_min REAL NOT NULL,
+    system_in_avg REAL NOT NULL,
+    system_in_max REAL NOT NULL,
+    battery_power_min REAL NOT NULL,
+    battery_power_avg REAL NOT NULL,
+    battery_power_max REAL NOT NULL,
+    battery_level_min REAL NOT NULL,
+    battery_level_avg REAL NOT NULL,
+    battery_level_max REAL NOT NULL,
+    PRIMARY KEY (udid, resolution, bucket)
+);
\ No newline at end of file
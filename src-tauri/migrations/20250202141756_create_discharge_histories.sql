@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS discharge_histories (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    udid TEXT NOT NULL,
+    name TEXT NOT NULL,
+    is_remote INTEGER NOT NULL,
+    from_level INTEGER NOT NULL,
+    end_level INTEGER NOT NULL,
+    discharging_time INTEGER NOT NULL,
+    avg_system_load REAL NOT NULL,
+    peak_system_load REAL NOT NULL,
+    energy REAL NOT NULL,
+    estimated_runtime INTEGER NULL,
+    timestamp INTEGER NOT NULL
+);
\ No newline at end of file
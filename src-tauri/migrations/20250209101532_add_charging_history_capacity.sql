@@ -0,0 +1,13 @@
+ALTER TABLE charging_histories ADD COLUMN end_timestamp INTEGER;
+ALTER TABLE charging_histories ADD COLUMN end_cycle_count INTEGER;
+ALTER TABLE charging_histories ADD COLUMN end_max_capacity INTEGER;
+ALTER TABLE charging_histories ADD COLUMN end_design_capacity INTEGER;
+
+-- so far the capacity was only kept in the last sample of the curve
+UPDATE charging_histories
+SET
+    end_timestamp = json_extract(CAST(detail AS TEXT), '$.curve[#-1].lastUpdate'),
+    end_cycle_count = json_extract(CAST(detail AS TEXT), '$.curve[#-1].cycleCount'),
+    end_max_capacity = json_extract(CAST(detail AS TEXT), '$.curve[#-1].maxCapacity'),
+    end_design_capacity = COALESCE(json_extract(CAST(detail AS TEXT), '$.curve[#-1].designCapacity'), 0)
+WHERE json_valid(CAST(detail AS TEXT));
\ No newline at end of file
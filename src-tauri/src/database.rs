@@ -12,8 +12,9 @@ use tauri::{
     AppHandle, Manager,
 };
 use tokio::task::block_in_place;
+use tpower::health::CapacitySample;
 
-use crate::history;
+use crate::{history, power_log, snapshot};
 
 static DEFAULT_DATABASE_NAME: &str = "db.sqlite";
 
@@ -50,6 +51,38 @@ pub async fn get_detail_by_id(conn: &SqlitePool, id: i64) -> Result<Vec<u8>, Str
         .map_err(|e| e.to_string())
 }
 
+/// The capacity at the end of a charge, kept next to the detail so the
+/// health report doesn't have to parse every curve.
+struct ChargeCapacity {
+    end_timestamp: i64,
+    end_cycle_count: i64,
+    end_max_capacity: i64,
+    end_design_capacity: i64,
+}
+
+pub async fn get_capacities_by_udid(
+    conn: &SqlitePool,
+    udid: &str,
+) -> Result<Vec<CapacitySample>, sqlx::Error> {
+    query_as!(
+        ChargeCapacity,
+        "SELECT end_timestamp AS \"end_timestamp!\", end_cycle_count AS \"end_cycle_count!\", end_max_capacity AS \"end_max_capacity!\", end_design_capacity AS \"end_design_capacity!\" FROM charging_histories WHERE udid = ? AND end_timestamp IS NOT NULL",
+        udid
+    )
+    .fetch_all(conn)
+    .await
+    .map(|rows| {
+        rows.into_iter()
+            .map(|row| CapacitySample {
+                timestamp: row.end_timestamp,
+                cycle_count: row.end_cycle_count as i32,
+                max_capacity: row.end_max_capacity as i32,
+                design_capacity: row.end_design_capacity as i32,
+            })
+            .collect()
+    })
+}
+
 pub async fn delete_history_by_id(
     conn: &SqlitePool,
     id: i64,
@@ -65,8 +98,13 @@ pub async fn save_charging_history(
 ) -> Result<SqliteQueryResult, sqlx::Error> {
     let detail = serde_json::to_vec(&history.detail).unwrap();
     let duration = history.duration;
+    let capacity = history.detail.capacity();
+    let end_timestamp = capacity.map(|c| c.timestamp);
+    let end_cycle_count = capacity.map(|c| c.cycle_count);
+    let end_max_capacity = capacity.map(|c| c.max_capacity);
+    let end_design_capacity = capacity.map(|c| c.design_capacity);
     query!(
-        "INSERT INTO charging_histories (from_level, end_level, charging_time, timestamp, detail, name, udid, is_remote, adapter_name) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        "INSERT INTO charging_histories (from_level, end_level, charging_time, timestamp, detail, name, udid, is_remote, adapter_name, end_timestamp, end_cycle_count, end_max_capacity, end_design_capacity) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
         history.from_level,
         history.end_level,
         duration,
@@ -75,12 +113,306 @@ pub async fn save_charging_history(
         history.name,
         history.udid,
         history.is_remote,
-        history.adapter_name
+        history.adapter_name,
+        end_timestamp,
+        end_cycle_count,
+        end_max_capacity,
+        end_design_capacity
+    )
+    .execute(conn)
+    .await
+}
+
+#[derive(Debug, sqlx::FromRow, Type, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DischargeHistory {
+    id: i64,
+    from_level: i64,
+    end_level: i64,
+    discharging_time: i64,
+    timestamp: i64,
+    name: String,
+    udid: String,
+    is_remote: i64,
+    /// W
+    avg_system_load: f64,
+    peak_system_load: f64,
+    /// Wh drawn from the battery
+    energy: f64,
+    /// Seconds a full battery lasts at the rate of this discharge, unknown
+    /// when the level didn't drop
+    estimated_runtime: Option<i64>,
+}
+
+pub async fn get_all_discharge_history(
+    conn: &SqlitePool,
+) -> Result<Vec<DischargeHistory>, sqlx::Error> {
+    query_as!(
+        DischargeHistory,
+        "SELECT id, from_level, end_level, discharging_time, timestamp, name, udid, is_remote, avg_system_load, peak_system_load, energy, estimated_runtime FROM discharge_histories ORDER BY timestamp DESC"
+    )
+    .fetch_all(conn)
+    .await
+}
+
+pub async fn delete_discharge_history_by_id(
+    conn: &SqlitePool,
+    id: i64,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    query!("DELETE FROM discharge_histories WHERE id = ?", id)
+        .execute(conn)
+        .await
+}
+
+pub async fn save_discharge_history(
+    conn: &SqlitePool,
+    history: &history::DischargeHistory,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    let duration = history.duration;
+    query!(
+        "INSERT INTO discharge_histories (from_level, end_level, discharging_time, timestamp, name, udid, is_remote, avg_system_load, peak_system_load, energy, estimated_runtime) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        history.from_level,
+        history.end_level,
+        duration,
+        history.timestamp,
+        history.name,
+        history.udid,
+        history.is_remote,
+        history.avg_system_load,
+        history.peak_system_load,
+        history.energy,
+        history.estimated_runtime
     )
     .execute(conn)
     .await
 }
 
+#[derive(Debug, sqlx::FromRow, Type, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatterySnapshot {
+    id: i64,
+    udid: String,
+    name: String,
+    is_remote: i64,
+    /// `YYYY-MM-DD` in local time
+    day: String,
+    max_capacity: i64,
+    design_capacity: i64,
+    cycle_count: i64,
+    min_temperature: f64,
+    max_temperature: f64,
+    adapter_name: Option<String>,
+    timestamp: i64,
+}
+
+impl BatterySnapshot {
+    pub fn capacity(&self) -> CapacitySample {
+        CapacitySample {
+            timestamp: self.timestamp,
+            cycle_count: self.cycle_count as i32,
+            max_capacity: self.max_capacity as i32,
+            design_capacity: self.design_capacity as i32,
+        }
+    }
+}
+
+pub async fn get_battery_snapshots(
+    conn: &SqlitePool,
+    udid: &str,
+) -> Result<Vec<BatterySnapshot>, sqlx::Error> {
+    query_as!(
+        BatterySnapshot,
+        "SELECT id, udid, name, is_remote, day, max_capacity, design_capacity, cycle_count, min_temperature, max_temperature, adapter_name, timestamp FROM battery_snapshots WHERE udid = ? ORDER BY day ASC",
+        udid
+    )
+    .fetch_all(conn)
+    .await
+}
+
+/// Insert the snapshot of its day, or merge it into the one already stored.
+pub async fn save_battery_snapshot(
+    conn: &SqlitePool,
+    snapshot: &snapshot::DaySnapshot,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    query!(
+        "INSERT INTO battery_snapshots (udid, name, is_remote, day, max_capacity, design_capacity, cycle_count, min_temperature, max_temperature, adapter_name, timestamp) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) ON CONFLICT (udid, day) DO UPDATE SET name = excluded.name, max_capacity = excluded.max_capacity, design_capacity = excluded.design_capacity, cycle_count = excluded.cycle_count, min_temperature = MIN(min_temperature, excluded.min_temperature), max_temperature = MAX(max_temperature, excluded.max_temperature), adapter_name = COALESCE(excluded.adapter_name, adapter_name), timestamp = excluded.timestamp",
+        snapshot.udid,
+        snapshot.name,
+        snapshot.is_remote,
+        snapshot.day,
+        snapshot.max_capacity,
+        snapshot.design_capacity,
+        snapshot.cycle_count,
+        snapshot.min_temperature,
+        snapshot.max_temperature,
+        snapshot.adapter_name,
+        snapshot.timestamp
+    )
+    .execute(conn)
+    .await
+}
+
+#[derive(Debug, Clone, sqlx::FromRow, Type, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PowerLogBucket {
+    /// Start of the bucket, seconds since the unix epoch
+    bucket: i64,
+    samples: i64,
+    system_load_min: f64,
+    system_load_avg: f64,
+    system_load_max: f64,
+    system_in_min: f64,
+    system_in_avg: f64,
+    system_in_max: f64,
+    battery_power_min: f64,
+    battery_power_avg: f64,
+    battery_power_max: f64,
+    battery_level_min: f64,
+    battery_level_avg: f64,
+    battery_level_max: f64,
+}
+
+struct PowerSample {
+    timestamp: i64,
+    system_load: f64,
+    system_in: f64,
+    battery_power: f64,
+    battery_level: i64,
+}
+
+impl From<PowerSample> for PowerLogBucket {
+    fn from(sample: PowerSample) -> Self {
+        let level = sample.battery_level as f64;
+        Self {
+            bucket: sample.timestamp,
+            samples: 1,
+            system_load_min: sample.system_load,
+            system_load_avg: sample.system_load,
+            system_load_max: sample.system_load,
+            system_in_min: sample.system_in,
+            system_in_avg: sample.system_in,
+            system_in_max: sample.system_in,
+            battery_power_min: sample.battery_power,
+            battery_power_avg: sample.battery_power,
+            battery_power_max: sample.battery_power,
+            battery_level_min: level,
+            battery_level_avg: level,
+            battery_level_max: level,
+        }
+    }
+}
+
+/// Raw samples of `udid` within `from..to`, each as a bucket of its own.
+pub async fn get_power_samples(
+    conn: &SqlitePool,
+    udid: &str,
+    from: i64,
+    to: i64,
+) -> Result<Vec<PowerLogBucket>, sqlx::Error> {
+    query_as!(
+        PowerSample,
+        "SELECT timestamp, system_load, system_in, battery_power, battery_level FROM power_samples WHERE udid = ? AND timestamp >= ? AND timestamp < ? ORDER BY timestamp ASC",
+        udid,
+        from,
+        to
+    )
+    .fetch_all(conn)
+    .await
+    .map(|samples| samples.into_iter().map(PowerLogBucket::from).collect())
+}
+
+/// Buckets of `resolution` seconds of `udid` starting within `from..to`.
+pub async fn get_power_rollups(
+    conn: &SqlitePool,
+    udid: &str,
+    resolution: i64,
+    from: i64,
+    to: i64,
+) -> Result<Vec<PowerLogBucket>, sqlx::Error> {
+    query_as!(
+        PowerLogBucket,
+        "SELECT bucket, samples, system_load_min, system_load_avg, system_load_max, system_in_min, system_in_avg, system_in_max, battery_power_min, battery_power_avg, battery_power_max, battery_level_min, battery_level_avg, battery_level_max FROM power_rollups WHERE udid = ? AND resolution = ? AND bucket >= ? AND bucket < ? ORDER BY bucket ASC",
+        udid,
+        resolution,
+        from,
+        to
+    )
+    .fetch_all(conn)
+    .await
+}
+
+pub async fn save_power_samples(
+    conn: &SqlitePool,
+    samples: &[power_log::LoggedSample],
+) -> Result<(), sqlx::Error> {
+    let mut tx = conn.begin().await?;
+    for sample in samples {
+        query!(
+            "INSERT INTO power_samples (udid, timestamp, system_load, system_in, battery_power, battery_level, is_charging) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            sample.udid,
+            sample.timestamp,
+            sample.system_load,
+            sample.system_in,
+            sample.battery_power,
+            sample.battery_level,
+            sample.is_charging
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await
+}
+
+/// Recompute the minute buckets of the raw samples within `from..to`, and
+/// the hour buckets of the minute buckets within `hours_from..hours_to`.
+pub async fn roll_up_power_log(
+    conn: &SqlitePool,
+    (from, to): (i64, i64),
+    (hours_from, hours_to): (i64, i64),
+) -> Result<(), sqlx::Error> {
+    let mut tx = conn.begin().await?;
+    query!(
+        "INSERT INTO power_rollups (udid, resolution, bucket, samples, system_load_min, system_load_avg, system_load_max, system_in_min, system_in_avg, system_in_max, battery_power_min, battery_power_avg, battery_power_max, battery_level_min, battery_level_avg, battery_level_max) SELECT udid, 60, timestamp / 60 * 60, COUNT(*), MIN(system_load), AVG(system_load), MAX(system_load), MIN(system_in), AVG(system_in), MAX(system_in), MIN(battery_power), AVG(battery_power), MAX(battery_power), MIN(battery_level), AVG(battery_level), MAX(battery_level) FROM power_samples WHERE timestamp >= ? AND timestamp < ? GROUP BY udid, timestamp / 60 ON CONFLICT (udid, resolution, bucket) DO UPDATE SET samples = excluded.samples, system_load_min = excluded.system_load_min, system_load_avg = excluded.system_load_avg, system_load_max = excluded.system_load_max, system_in_min = excluded.system_in_min, system_in_avg = excluded.system_in_avg, system_in_max = excluded.system_in_max, battery_power_min = excluded.battery_power_min, battery_power_avg = excluded.battery_power_avg, battery_power_max = excluded.battery_power_max, battery_level_min = excluded.battery_level_min, battery_level_avg = excluded.battery_level_avg, battery_level_max = excluded.battery_level_max",
+        from,
+        to
+    )
+    .execute(&mut *tx)
+    .await?;
+    query!(
+        "INSERT INTO power_rollups (udid, resolution, bucket, samples, system_load_min, system_load_avg, system_load_max, system_in_min, system_in_avg, system_in_max, battery_power_min, battery_power_avg, battery_power_max, battery_level_min, battery_level_avg, battery_level_max) SELECT udid, 3600, bucket / 3600 * 3600, SUM(samples), MIN(system_load_min), SUM(system_load_avg * samples) / SUM(samples), MAX(system_load_max), MIN(system_in_min), SUM(system_in_avg * samples) / SUM(samples), MAX(system_in_max), MIN(battery_power_min), SUM(battery_power_avg * samples) / SUM(samples), MAX(battery_power_max), MIN(battery_level_min), SUM(battery_level_avg * samples) / SUM(samples), MAX(battery_level_max) FROM power_rollups WHERE resolution = 60 AND bucket >= ? AND bucket < ? GROUP BY udid, bucket / 3600 ON CONFLICT (udid, resolution, bucket) DO UPDATE SET samples = excluded.samples, system_load_min = excluded.system_load_min, system_load_avg = excluded.system_load_avg, system_load_max = excluded.system_load_max, system_in_min = excluded.system_in_min, system_in_avg = excluded.system_in_avg, system_in_max = excluded.system_in_max, battery_power_min = excluded.battery_power_min, battery_power_avg = excluded.battery_power_avg, battery_power_max = excluded.battery_power_max, battery_level_min = excluded.battery_level_min, battery_level_avg = excluded.battery_level_avg, battery_level_max = excluded.battery_level_max",
+        hours_from,
+        hours_to
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await
+}
+
+/// Drop raw samples older than `samples_before`, and for each
+/// `(resolution, before)` the buckets older than `before`.
+pub async fn expire_power_log(
+    conn: &SqlitePool,
+    samples_before: i64,
+    rollups_before: &[(i64, i64)],
+) -> Result<(), sqlx::Error> {
+    query!(
+        "DELETE FROM power_samples WHERE timestamp < ?",
+        samples_before
+    )
+    .execute(conn)
+    .await?;
+    for (resolution, before) in rollups_before {
+        query!(
+            "DELETE FROM power_rollups WHERE resolution = ? AND bucket < ?",
+            resolution,
+            before
+        )
+        .execute(conn)
+        .await?;
+    }
+    Ok(())
+}
+
 pub fn setup_database(app: AppHandle) {
     block_in_place(|| {
         async_runtime::block_on(async move {
@@ -2,8 +2,8 @@ use std::{
     collections::{HashMap, HashSet},
     ffi::c_void,
     mem::{self, MaybeUninit},
-    sync::{Arc, RwLock},
-    time::Duration,
+    sync::{Arc, PoisonError, RwLock},
+    time::{Duration, SystemTime},
 };
 
 use derive_more::derive::Deref;
@@ -13,12 +13,12 @@ use tauri::{async_runtime, AppHandle, Manager};
 use tauri_specta::Event;
 use tokio::{select, sync::mpsc, task::spawn_blocking, time};
 use tpower::{
+    estimate::{Estimate, Estimator},
     ffi::{
-        core_foundation::runloop::CFRunLoopRun,
-        wrapper::{Device, ServiceConnection},
-        AMDeviceNotificationCallbackInfo, AMDeviceNotificationSubscribe, Action, InterfaceType,
+        core_foundation::runloop::CFRunLoopRun, wrapper::Device, AMDeviceNotificationCallbackInfo,
+        AMDeviceNotificationSubscribe, Action, InterfaceType,
     },
-    provider::{remote::get_device_ioreg, NormalizedResource},
+    provider::{remote::RemoteSource, BatteryDetails, NormalizedResource, PowerSource},
 };
 
 use crate::event::DeviceEvent;
@@ -26,11 +26,41 @@ use crate::event::DeviceEvent;
 #[derive(Default, Deref)]
 pub struct DeviceState(RwLock<HashMap<String, (String, HashSet<InterfaceType>)>>);
 
+/// The latest [`BatteryDetails`] by udid, `local` for this Mac. They are
+/// left out of the tick events and served by `get_battery_details` instead.
+#[derive(Default)]
+pub struct BatteryDetailsState(RwLock<HashMap<String, BatteryDetails>>);
+
+impl BatteryDetailsState {
+    pub fn get(&self, udid: &str) -> Option<BatteryDetails> {
+        self.0
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(udid)
+            .cloned()
+    }
+
+    pub fn update(&self, udid: &str, details: &BatteryDetails) {
+        self.0
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(udid.to_string(), details.clone());
+    }
+
+    pub fn remove(&self, udid: &str) {
+        self.0
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(udid);
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Event, Type)]
 #[serde(rename_all = "camelCase")]
 pub struct DevicePowerTickEvent {
     pub udid: String,
     pub data: NormalizedResource,
+    pub estimate: Option<Estimate>,
 }
 
 #[derive(Debug)]
@@ -82,18 +112,24 @@ pub fn start_device_sender(handle: AppHandle) -> async_runtime::JoinHandle<()> {
     let mut rx = start_device_listener();
     let mut timer = time::interval(Duration::from_millis(2000));
 
-    let mut devices: HashMap<Device, ServiceConnection> = HashMap::new();
+    let mut devices: HashMap<Device, (RemoteSource, Estimator)> = HashMap::new();
 
     async_runtime::spawn(async move {
         loop {
             select! {
                 _ = timer.tick() => {
-                    for (device, conn) in devices.iter() {
-                        match get_device_ioreg(conn) {
-                            Ok(res) => DevicePowerTickEvent {
-                                udid: device.udid.clone(),
-                                data: NormalizedResource::from(&res),
-                            }.emit(&handle).unwrap(),
+                    for (device, (source, estimator)) in devices.iter_mut() {
+                        match source.sample_normalized() {
+                            Ok(data) => {
+                                handle
+                                    .state::<BatteryDetailsState>()
+                                    .update(&device.udid, &data.battery);
+                                DevicePowerTickEvent {
+                                    udid: device.udid.clone(),
+                                    estimate: estimator.update(&data, SystemTime::now()),
+                                    data,
+                                }.emit(&handle).unwrap();
+                            }
                             Err(err) => {
                                 log::error!("Failed to get IORegistry: {err}");
                             }
@@ -103,20 +139,29 @@ pub fn start_device_sender(handle: AppHandle) -> async_runtime::JoinHandle<()> {
                 Some(DeviceMessage { device, action }) = rx.recv() => {
                     match action {
                         Action::Attached => {
-                            // unwrap pair
-                            device.prepare_device().unwrap();
-                            let conn = device.start_service("com.apple.mobile.diagnostics_relay");
+                            // must create the source after `device.prepare_device()`
+                            // or name will be empty
+                            let source = device
+                                .prepare_device()
+                                .and_then(|_| RemoteSource::new(&device));
+                            let source = match source {
+                                Ok(source) => source,
+                                Err(err) => {
+                                    // e.g. locked or not trusting this Mac yet,
+                                    // it is picked up again on the next attach
+                                    log::warn!("Failed to start device {}: {err}", device.udid);
+                                    continue;
+                                }
+                            };
 
                             DeviceEvent {
                                 udid: device.udid.clone(),
-                                // must call `device.name()` after `device.prepare_device()`
-                                // or name will be empty causing panic
-                                name: device.name(),
+                                name: source.name().to_string(),
                                 interface: device.interface_type,
                                 action,
                             }.emit(&handle).unwrap();
 
-                            devices.insert(device, conn);
+                            devices.insert(device, (source, Estimator::default()));
                         },
                         Action::Detached => {
                             log::debug!("Device detached: {}", device.udid);
@@ -126,6 +171,7 @@ pub fn start_device_sender(handle: AppHandle) -> async_runtime::JoinHandle<()> {
                                 interface: device.interface_type,
                                 action,
                             }.emit(&handle).unwrap();
+                            handle.state::<BatteryDetailsState>().remove(&device.udid);
                             devices.remove(&device);
                         },
                         _ => ()
@@ -28,6 +28,9 @@ pub enum PreferenceEvent {
     Language(String),
     StatusBarItem(StatusBarItem),
     StatusBarShowCharging(bool),
+    MetricsExporter(bool),
+    MetricsPort(u16),
+    MetricsAllowRemote(bool),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Event, Type)]
@@ -0,0 +1,181 @@
+use std::{
+    collections::HashMap,
+    net::{Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use tauri::{AppHandle, Manager};
+use tauri_plugin_pinia::ManagerExt;
+use tauri_specta::Event;
+use tpower::{
+    export::{Metrics, MetricsServer},
+    ffi::Action,
+    provider::PowerDataFrom,
+};
+
+use crate::{
+    device::DevicePowerTickEvent,
+    event::{DeviceEvent, PreferenceEvent},
+    local::PowerTickEvent,
+};
+
+pub const DEFAULT_METRICS_PORT: u16 = 9101;
+
+/// Opt-in Prometheus endpoint serving the samples the app already emits.
+#[derive(Default)]
+pub struct ExporterState {
+    metrics: Arc<Metrics>,
+    server: Mutex<Option<MetricsServer>>,
+    port: Mutex<u16>,
+    /// Listen on every interface instead of only loopback
+    allow_remote: Mutex<bool>,
+    /// udid -> where its samples come from, kept from the attach event since
+    /// [`DevicePowerTickEvent`] only carries the udid
+    devices: Mutex<HashMap<String, PowerDataFrom>>,
+}
+
+impl ExporterState {
+    fn is_enabled(&self) -> bool {
+        self.server
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_some()
+    }
+
+    fn start(&self) {
+        let port = *self.port.lock().unwrap_or_else(PoisonError::into_inner);
+        let allow_remote = *self
+            .allow_remote
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut server = self.server.lock().unwrap_or_else(PoisonError::into_inner);
+        // drop the old server first so the port is free again
+        server.take();
+
+        // every device's udid, name and power is in there, so it stays on
+        // this machine unless asked otherwise
+        let ip = if allow_remote {
+            Ipv4Addr::UNSPECIFIED
+        } else {
+            Ipv4Addr::LOCALHOST
+        };
+        let addr = SocketAddr::from((ip, port));
+        match MetricsServer::bind(addr, self.metrics.clone()) {
+            Ok(s) => {
+                log::info!("Serving metrics at http://{}/metrics", s.local_addr());
+                *server = Some(s);
+            }
+            Err(err) => log::error!("Failed to start metrics exporter on {addr}: {err}"),
+        }
+    }
+
+    fn stop(&self) {
+        self.server
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+    }
+}
+
+pub fn setup_exporter(app: AppHandle) {
+    let state = app.state::<ExporterState>();
+
+    *state.port.lock().unwrap_or_else(PoisonError::into_inner) = app
+        .pinia()
+        .try_get::<u16>("preference", "metricsPort")
+        .unwrap_or(DEFAULT_METRICS_PORT);
+    *state
+        .allow_remote
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = app
+        .pinia()
+        .try_get::<bool>("preference", "metricsAllowRemote")
+        .unwrap_or(false);
+    if app
+        .pinia()
+        .try_get::<bool>("preference", "metricsExporter")
+        .unwrap_or(false)
+    {
+        state.start();
+    }
+
+    let handle = app.clone();
+    PreferenceEvent::listen(&app, move |event| {
+        let state = handle.state::<ExporterState>();
+        match event.payload {
+            PreferenceEvent::MetricsExporter(true) => state.start(),
+            PreferenceEvent::MetricsExporter(false) => state.stop(),
+            PreferenceEvent::MetricsPort(port) => {
+                *state.port.lock().unwrap_or_else(PoisonError::into_inner) = port;
+                if state.is_enabled() {
+                    state.start();
+                }
+            }
+            PreferenceEvent::MetricsAllowRemote(allow) => {
+                *state
+                    .allow_remote
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner) = allow;
+                if state.is_enabled() {
+                    state.start();
+                }
+            }
+            _ => (),
+        }
+    });
+
+    let handle = app.clone();
+    PowerTickEvent::listen(&app, move |event| {
+        let state = handle.state::<ExporterState>();
+        if state.is_enabled() {
+            state
+                .metrics
+                .update(&PowerDataFrom::Local, &event.payload.data);
+        }
+    });
+
+    let handle = app.clone();
+    DevicePowerTickEvent::listen(&app, move |event| {
+        let state = handle.state::<ExporterState>();
+        if !state.is_enabled() {
+            return;
+        }
+        if let Some(from) = state
+            .devices
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&event.payload.udid)
+        {
+            state.metrics.update(from, &event.payload.data);
+        }
+    });
+
+    let handle = app.clone();
+    DeviceEvent::listen(&app, move |event| {
+        let state = handle.state::<ExporterState>();
+        let DeviceEvent {
+            udid,
+            name,
+            interface,
+            action,
+        } = event.payload;
+        let mut devices = state.devices.lock().unwrap_or_else(PoisonError::into_inner);
+        match action {
+            Action::Attached => {
+                devices.insert(udid.clone(), PowerDataFrom::Remote((udid, name, interface)));
+            }
+            Action::Detached => {
+                // a device attached over both USB and WiFi keeps its entry
+                // until the interface it was sampled over goes away
+                if let Some(PowerDataFrom::Remote((_, _, sampled))) = devices.get(&udid) {
+                    if *sampled == interface {
+                        if let Some(from) = devices.remove(&udid) {
+                            state.metrics.remove(&from);
+                        }
+                    }
+                }
+            }
+            _ => (),
+        }
+    });
+}
@@ -7,16 +7,24 @@ use tauri::{async_runtime, AppHandle, Manager};
 use tauri_specta::{Event, TypedEvent};
 use tokio::sync::mpsc;
 use tpower::{
+    health::CapacitySample,
+    phase::{detect_phases, PhaseSpan},
     provider::{NormalizedData, NormalizedResource},
     util::get_mac_name,
 };
 
 use crate::{
-    database::save_charging_history,
+    database::{save_charging_history, save_discharge_history},
     device::{DevicePowerTickEvent, DeviceState},
     local::PowerTickEvent,
 };
 
+/// Discharges shorter than this aren't recorded, s.
+const MIN_DISCHARGE_DURATION: i64 = 5 * 60;
+/// A discharge is closed when its device goes silent for longer than this,
+/// rather than integrating the energy across the gap, s.
+const MAX_SAMPLE_GAP: i64 = 5 * 60;
+
 struct ChargingHistoryStage {
     data: NormalizedResource,
     raw: String,
@@ -41,6 +49,78 @@ pub struct ChargingHistoryDetail {
     peak: NormalizedData,
     curve: Vec<NormalizedResource>,
     raw: Vec<String>,
+    #[serde(default)]
+    phases: Vec<PhaseSpan>,
+}
+
+impl ChargingHistoryDetail {
+    /// The capacity at the end of the charge.
+    pub fn capacity(&self) -> Option<CapacitySample> {
+        self.curve.last().map(CapacitySample::from)
+    }
+
+    /// Histories recorded before phases were stored get them on read.
+    pub fn fill_phases(&mut self) {
+        if self.phases.is_empty() {
+            self.phases = detect_phases(&self.curve);
+        }
+    }
+}
+
+pub struct DischargeHistory {
+    pub is_remote: bool,
+    pub name: String,
+    pub udid: String,
+    pub from_level: i32,
+    pub end_level: i32,
+    pub duration: i64,
+    pub timestamp: i64,
+    pub avg_system_load: f32,
+    pub peak_system_load: f32,
+    /// Wh
+    pub energy: f32,
+    /// How long a full battery lasts at the rate of this discharge, s
+    pub estimated_runtime: Option<i64>,
+}
+
+/// A discharge may last for hours, so it is summarized as samples come in
+/// instead of keeping them.
+struct DischargeStage {
+    first: NormalizedResource,
+    last: NormalizedResource,
+    samples: usize,
+    load_sum: f32,
+    peak_load: f32,
+    /// Wh
+    energy: f32,
+}
+
+impl DischargeStage {
+    fn new(data: NormalizedResource) -> Self {
+        Self {
+            samples: 1,
+            load_sum: data.system_load,
+            peak_load: data.system_load,
+            energy: 0.,
+            first: data.clone(),
+            last: data,
+        }
+    }
+
+    /// Whether `data` comes too long after the last sample to be part of
+    /// the same discharge.
+    fn is_stale(&self, data: &NormalizedResource) -> bool {
+        data.last_update - self.last.last_update > MAX_SAMPLE_GAP
+    }
+
+    fn push(&mut self, data: NormalizedResource) {
+        let hours = (data.last_update - self.last.last_update) as f32 / 3600.;
+        self.energy += (self.last.system_load + data.system_load) / 2. * hours;
+        self.samples += 1;
+        self.load_sum += data.system_load;
+        self.peak_load = self.peak_load.max(data.system_load);
+        self.last = data;
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, Type, Event)]
@@ -52,21 +132,34 @@ enum DeviceType {
     Remote(String),
 }
 
+impl DeviceType {
+    fn name(&self, app: &AppHandle) -> String {
+        match self {
+            DeviceType::Local => get_mac_name(),
+            DeviceType::Remote(udid) => app
+                .state::<DeviceState>()
+                .read()
+                .unwrap()
+                .get(udid)
+                .map(|d| d.0.clone()),
+        }
+        .unwrap_or_default()
+    }
+
+    fn udid(&self) -> String {
+        match self {
+            DeviceType::Local => "local".to_string(),
+            DeviceType::Remote(udid) => udid.clone(),
+        }
+    }
+}
+
 fn summrize_history(
     app: &AppHandle,
     staged: Vec<ChargingHistoryStage>,
     typ: DeviceType,
 ) -> Option<ChargingHistory> {
-    let name = match typ {
-        DeviceType::Local => get_mac_name(),
-        DeviceType::Remote(ref udid) => app
-            .state::<DeviceState>()
-            .read()
-            .unwrap()
-            .get(udid)
-            .map(|d| d.0.clone()),
-    }
-    .unwrap_or_default();
+    let name = typ.name(app);
 
     let (first, last) = (staged.first()?, staged.last()?);
 
@@ -88,15 +181,13 @@ fn summrize_history(
     let peak = staged.iter().fold(NormalizedData::default(), |acc, cur| {
         acc.max_with(&cur.data)
     });
-    let (curve, raw) = staged.into_iter().map(|s| (s.data, s.raw)).unzip();
+    let (curve, raw): (Vec<_>, _) = staged.into_iter().map(|s| (s.data, s.raw)).unzip();
+    let phases = detect_phases(&curve);
 
     Some(ChargingHistory {
         is_remote: matches!(typ, DeviceType::Remote(_)),
         name,
-        udid: match typ {
-            DeviceType::Local => "local".to_string(),
-            DeviceType::Remote(ref udid) => udid.clone(),
-        },
+        udid: typ.udid(),
         from_level,
         end_level,
         duration,
@@ -107,10 +198,67 @@ fn summrize_history(
             peak,
             curve,
             raw,
+            phases,
         },
     })
 }
 
+fn summrize_discharge(
+    stage: DischargeStage,
+    typ: &DeviceType,
+    name: impl FnOnce() -> String,
+) -> Option<DischargeHistory> {
+    let duration = stage.last.last_update - stage.first.last_update;
+    if stage.samples <= 2 || duration < MIN_DISCHARGE_DURATION {
+        return None;
+    }
+
+    let from_level = stage.first.battery_level;
+    let end_level = stage.last.battery_level;
+    let drained = from_level - end_level;
+
+    Some(DischargeHistory {
+        is_remote: matches!(typ, DeviceType::Remote(_)),
+        name: name(),
+        udid: typ.udid(),
+        from_level,
+        end_level,
+        duration,
+        timestamp: stage.first.last_update,
+        avg_system_load: stage.load_sum / stage.samples as f32,
+        peak_system_load: stage.peak_load,
+        energy: stage.energy,
+        estimated_runtime: (drained > 0).then(|| duration * 100 / drained as i64),
+    })
+}
+
+async fn record_discharge(
+    app: &AppHandle,
+    db: &SqlitePool,
+    stage: DischargeStage,
+    typ: &DeviceType,
+) {
+    let Some(history) = summrize_discharge(stage, typ, || typ.name(app)) else {
+        return;
+    };
+    match save_discharge_history(db, &history).await {
+        Ok(res) => {
+            log::info!(
+                "discharge of {} saved: {}",
+                history.udid,
+                res.last_insert_rowid()
+            );
+        }
+        Err(e) => {
+            log::error!("discharge save failed: {:#?}", e);
+        }
+    }
+
+    HistoryRecordedEvent
+        .emit(app)
+        .unwrap_or_else(|err| log::error!("Failed to emit HistoryRecordedEvent: {:?}", err));
+}
+
 fn spawn_history_recorder(
     app: AppHandle,
     mut rx: mpsc::Receiver<(DeviceType, NormalizedResource)>,
@@ -118,8 +266,24 @@ fn spawn_history_recorder(
     async_runtime::spawn(async move {
         let db = app.state::<SqlitePool>();
         let mut staged: HashMap<DeviceType, Vec<ChargingHistoryStage>> = HashMap::new();
+        let mut discharges: HashMap<DeviceType, DischargeStage> = HashMap::new();
 
         while let Some((typ, data)) = rx.recv().await {
+            if !data.is_discharging() {
+                if let Some(stage) = discharges.remove(&typ) {
+                    record_discharge(app.app_handle(), &db, stage, &typ).await;
+                }
+            } else if let Some(stage) = discharges.get_mut(&typ) {
+                if stage.is_stale(&data) {
+                    let stage = mem::replace(stage, DischargeStage::new(data.clone()));
+                    record_discharge(app.app_handle(), &db, stage, &typ).await;
+                } else if data.last_update != stage.last.last_update {
+                    stage.push(data.clone());
+                }
+            } else {
+                discharges.insert(typ.clone(), DischargeStage::new(data.clone()));
+            }
+
             let full_charged = data.battery_level == 100;
 
             let staged = staged.entry(typ.clone()).or_default();
@@ -200,3 +364,99 @@ pub fn setup_history_recorder(app: AppHandle) {
     });
     spawn_history_recorder(app.clone(), rx);
 }
+
+#[cfg(test)]
+mod tests {
+    use tpower::provider::NormalizedData;
+
+    use super::*;
+
+    /// On battery, `minute` minutes in.
+    fn sample(minute: i64, battery_level: i32, system_load: f32) -> NormalizedResource {
+        NormalizedResource {
+            last_update: 1_700_000_000 + minute * 60,
+            external_connected: Some(false),
+            data: NormalizedData {
+                battery_level,
+                system_load,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    /// A stage of a sample a minute from `samples` of `(battery_level,
+    /// system_load)`.
+    fn stage(samples: &[(i32, f32)]) -> DischargeStage {
+        let mut samples = samples
+            .iter()
+            .enumerate()
+            .map(|(minute, &(level, load))| sample(minute as i64, level, load));
+        let mut stage = DischargeStage::new(samples.next().unwrap());
+        samples.for_each(|data| stage.push(data));
+        stage
+    }
+
+    #[test]
+    fn integrates_energy_between_samples() {
+        // 6 W for the first half hour, then ramping up to 12 W
+        let mut stage = DischargeStage::new(sample(0, 80, 6.));
+        stage.push(sample(30, 75, 6.));
+        stage.push(sample(60, 70, 12.));
+
+        assert!((stage.energy - (3. + 4.5)).abs() < 1e-4);
+        assert_eq!(stage.samples, 3);
+        assert_eq!(stage.peak_load, 12.);
+    }
+
+    #[test]
+    fn goes_stale_after_a_gap() {
+        let stage = DischargeStage::new(sample(0, 80, 6.));
+
+        assert!(!stage.is_stale(&sample(5, 79, 6.)));
+        assert!(stage.is_stale(&sample(6, 79, 6.)));
+    }
+
+    #[test]
+    fn summarizes_a_discharge() {
+        let samples = (0..=10)
+            .map(|minute| (80 - minute, if minute == 4 { 30. } else { 10. }))
+            .collect::<Vec<_>>();
+        let history =
+            summrize_discharge(stage(&samples), &DeviceType::Local, || "Mac".to_string()).unwrap();
+
+        assert!(!history.is_remote);
+        assert_eq!(history.name, "Mac");
+        assert_eq!(history.udid, "local");
+        assert_eq!((history.from_level, history.end_level), (80, 70));
+        assert_eq!(history.duration, 10 * 60);
+        assert_eq!(history.timestamp, 1_700_000_000);
+        assert!((history.avg_system_load - 130. / 11.).abs() < 1e-4);
+        assert_eq!(history.peak_system_load, 30.);
+        // 10 W for 10 minutes, and the spike to 30 W a ramp up and down
+        // over 2 minutes
+        assert!((history.energy - (10. / 6. + 20. / 60.)).abs() < 1e-4);
+        // 10% in 10 minutes
+        assert_eq!(history.estimated_runtime, Some(100 * 60));
+    }
+
+    #[test]
+    fn skips_short_discharges() {
+        let short = stage(&[(80, 10.), (80, 10.), (79, 10.), (79, 10.)]);
+        assert!(summrize_discharge(short, &DeviceType::Local, String::new).is_none());
+
+        let sparse = stage(&[(80, 10.), (79, 10.)]);
+        assert!(summrize_discharge(sparse, &DeviceType::Local, String::new).is_none());
+    }
+
+    #[test]
+    fn leaves_out_the_runtime_without_a_drain() {
+        let flat = stage(&[(80, 1.); 6]);
+        let history =
+            summrize_discharge(flat, &DeviceType::Remote("udid".to_string()), String::new).unwrap();
+
+        assert!(history.is_remote);
+        assert_eq!(history.udid, "udid");
+        assert_eq!(history.estimated_runtime, None);
+    }
+}
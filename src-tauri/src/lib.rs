@@ -1,8 +1,12 @@
 use std::collections::HashSet;
 
-use database::{setup_database, ChargingHistory};
-use device::{setup_device_listener, start_device_sender, DevicePowerTickEvent, DeviceState};
+use database::{setup_database, BatterySnapshot, ChargingHistory, DischargeHistory};
+use device::{
+    setup_device_listener, start_device_sender, BatteryDetailsState, DevicePowerTickEvent,
+    DeviceState,
+};
 use event::{DeviceEvent, PowerUpdatedEvent, PreferenceEvent, Theme, WindowLoadedEvent};
+use exporter::{setup_exporter, ExporterState};
 use ext::WebviewWindowExt;
 use history::{setup_history_recorder, ChargingHistoryDetail, HistoryRecordedEvent};
 use local::{setup_sender_with_events, PowerTickEvent};
@@ -11,22 +15,31 @@ use objc2_app_kit::{
     NSAppearance, NSAppearanceCustomization, NSAppearanceNameVibrantDark,
     NSAppearanceNameVibrantLight, NSWindow,
 };
+use power_log::{setup_power_logger, PowerLog, PowerLogResolution};
+use snapshot::setup_snapshot_recorder;
 #[cfg(debug_assertions)]
 use specta_typescript::{BigIntExportBehavior, Typescript};
 use sqlx::{Pool, Sqlite};
 use tauri::{ActivationPolicy, AppHandle, Manager, RunEvent, State, Window, WindowEvent};
 use tauri_specta::{collect_commands, collect_events};
-use tpower::ffi::InterfaceType;
+use tpower::{
+    ffi::InterfaceType,
+    health::{HealthReport, DEFAULT_THRESHOLDS},
+    provider::BatteryDetails,
+};
 use tray_icon::setup_tray_icon;
 use util::setup_traffic_light_positioner;
 
 mod database;
 pub mod device;
 mod event;
+mod exporter;
 mod ext;
 mod history;
 mod local;
 mod menu;
+mod power_log;
+mod snapshot;
 mod tray_icon;
 mod util;
 
@@ -74,6 +87,22 @@ fn get_mac_name() -> Option<String> {
     tpower::util::get_mac_name()
 }
 
+/// What the battery of a device reports beyond the power readings, `local`
+/// for this Mac. `None` until the device has been sampled.
+#[tauri::command]
+#[specta::specta]
+fn get_battery_details(udid: String, state: State<BatteryDetailsState>) -> Option<BatteryDetails> {
+    state.get(&udid)
+}
+
+/// Everything `AppleSmartBattery` reports, for debugging and bug reports.
+#[tauri::command]
+#[specta::specta]
+fn get_raw_ioreg() -> Result<serde_json::Value, String> {
+    let raw = tpower::provider::get_mac_ioreg_raw().map_err(|e| e.to_string())?;
+    serde_json::to_value(raw).map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 #[specta::specta]
 fn switch_theme(theme: Theme, app: AppHandle) {
@@ -96,7 +125,9 @@ async fn get_detail_by_id(
     db: State<'_, Pool<Sqlite>>,
 ) -> Result<ChargingHistoryDetail, String> {
     let bytes = database::get_detail_by_id(&db, id).await?;
-    let detail = serde_json::from_slice(&bytes).map_err(|e| e.to_string())?;
+    let mut detail: ChargingHistoryDetail =
+        serde_json::from_slice(&bytes).map_err(|e| e.to_string())?;
+    detail.fill_phases();
 
     Ok(detail)
 }
@@ -120,6 +151,77 @@ async fn get_all_charging_history(
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+#[specta::specta]
+async fn delete_discharge_history_by_id(
+    id: i64,
+    db: State<'_, Pool<Sqlite>>,
+) -> Result<u64, String> {
+    database::delete_discharge_history_by_id(&db, id)
+        .await
+        .map(|v| v.rows_affected())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn get_all_discharge_history(
+    db: State<'_, Pool<Sqlite>>,
+) -> Result<Vec<DischargeHistory>, String> {
+    database::get_all_discharge_history(&db)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// State of health of a device, fitted over its charging history and daily
+/// snapshots.
+#[tauri::command]
+#[specta::specta]
+async fn get_battery_health(
+    udid: String,
+    db: State<'_, Pool<Sqlite>>,
+) -> Result<Option<HealthReport>, String> {
+    let charges = database::get_capacities_by_udid(&db, &udid)
+        .await
+        .map_err(|e| e.to_string())?;
+    let snapshots = database::get_battery_snapshots(&db, &udid)
+        .await
+        .map_err(|e| e.to_string())?;
+    let history = charges
+        .into_iter()
+        .chain(snapshots.iter().map(BatterySnapshot::capacity));
+
+    Ok(HealthReport::new(history, &DEFAULT_THRESHOLDS))
+}
+
+/// One row per day, oldest first.
+#[tauri::command]
+#[specta::specta]
+async fn get_battery_snapshots(
+    udid: String,
+    db: State<'_, Pool<Sqlite>>,
+) -> Result<Vec<BatterySnapshot>, String> {
+    database::get_battery_snapshots(&db, &udid)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Power log of a device within `from..to`, seconds since the unix epoch.
+/// Without a resolution the finest one still kept for the range is used.
+#[tauri::command]
+#[specta::specta]
+async fn get_power_log(
+    udid: String,
+    from: i64,
+    to: i64,
+    resolution: Option<PowerLogResolution>,
+    db: State<'_, Pool<Sqlite>>,
+) -> Result<PowerLog, String> {
+    power_log::query_power_log(&db, &udid, from, to, resolution)
+        .await
+        .map_err(|e| e.to_string())
+}
+
 pub fn create_specta() -> tauri_specta::Builder {
     let builder = tauri_specta::Builder::<tauri::Wry>::new()
         .commands(collect_commands![
@@ -128,10 +230,17 @@ pub fn create_specta() -> tauri_specta::Builder {
             open_settings,
             get_device_name,
             get_mac_name,
+            get_battery_details,
+            get_raw_ioreg,
             switch_theme,
             get_detail_by_id,
             get_all_charging_history,
-            delete_history_by_id
+            delete_history_by_id,
+            get_all_discharge_history,
+            delete_discharge_history_by_id,
+            get_battery_health,
+            get_battery_snapshots,
+            get_power_log
         ])
         .events(collect_events![
             DeviceEvent,
@@ -174,6 +283,8 @@ pub fn run() {
         .plugin(tauri_plugin_nspopover::init())
         .invoke_handler(specta.invoke_handler())
         .manage(DeviceState::default())
+        .manage(BatteryDetailsState::default())
+        .manage(ExporterState::default())
         .menu(setup_menu)
         .on_window_event(handle_window_event)
         .setup(move |app| {
@@ -186,6 +297,9 @@ pub fn run() {
             start_device_sender(app.app_handle().clone());
             setup_device_listener(app.app_handle().clone());
             setup_history_recorder(app.app_handle().clone());
+            setup_snapshot_recorder(app.app_handle().clone());
+            setup_power_logger(app.app_handle().clone());
+            setup_exporter(app.app_handle().clone());
 
             setup_traffic_light_positioner(app.main_window().unwrap());
 
@@ -1,17 +1,25 @@
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 use serde::{Deserialize, Serialize};
 use specta::Type;
-use tauri::{async_runtime, Manager, Runtime};
+use tauri::{async_runtime, AppHandle, Manager, Runtime};
 use tauri_plugin_pinia::ManagerExt;
 use tauri_specta::Event;
 use tokio::{select, sync::mpsc, time};
 use tpower::{
-    ffi::smc::{SMCConnection, SMCPowerData, SMCReadSensor},
-    provider::{get_mac_ioreg, NormalizedResource},
+    estimate::{Estimate, Estimator},
+    ffi::smc::SensorProfiles,
+    provider::{local::LocalSource, NormalizedResource, PowerSource},
 };
 
-use crate::event::{PowerUpdatedEvent, PreferenceEvent, StatusBarItem, WindowLoadedEvent};
+use crate::{
+    device::BatteryDetailsState,
+    event::{PowerUpdatedEvent, PreferenceEvent, StatusBarItem, WindowLoadedEvent},
+};
+
+/// SMC sensor profiles of the user in the app data directory, tried before
+/// the bundled ones.
+const USER_SMC_PROFILES: [&str; 2] = ["smc-profiles.toml", "smc-profiles.json"];
 
 pub enum SenderMessage {
     ImmediateSend,
@@ -21,17 +29,17 @@ pub enum SenderMessage {
 }
 
 pub fn status_bar_text(
-    smc: &SMCPowerData,
+    data: &NormalizedResource,
     status_bar_item: &StatusBarItem,
     show_charging: bool,
 ) -> f32 {
-    if smc.is_charging() && show_charging {
-        return smc.delivery_rate;
+    if data.is_charging && show_charging {
+        return data.system_in;
     }
     match status_bar_item {
-        StatusBarItem::System => smc.system_total,
-        StatusBarItem::Screen => smc.brightness,
-        StatusBarItem::Heatpipe => smc.heatpipe,
+        StatusBarItem::System => data.system_load,
+        StatusBarItem::Screen => data.brightness_power,
+        StatusBarItem::Heatpipe => data.heatpipe_power,
     }
 }
 
@@ -41,11 +49,11 @@ impl PowerUpdatedEvent {
     }
 
     pub fn new_with(
-        smc: &SMCPowerData,
+        data: &NormalizedResource,
         status_bar_item: &StatusBarItem,
         show_charging: bool,
     ) -> Self {
-        Self::new(status_bar_text(smc, status_bar_item, show_charging))
+        Self::new(status_bar_text(data, status_bar_item, show_charging))
     }
 }
 
@@ -53,14 +61,15 @@ impl PowerUpdatedEvent {
 #[serde(rename_all = "camelCase")]
 pub struct PowerTickEvent {
     pub data: NormalizedResource,
+    pub estimate: Option<Estimate>,
 }
 
 pub fn start_sender<R: Runtime>(
     app: &impl Manager<R>,
+    mut source: impl PowerSource + Send + 'static,
     mut rx: mpsc::Receiver<SenderMessage>,
 ) -> async_runtime::JoinHandle<()> {
     let app = app.app_handle().clone();
-    let mut smc_conn = SMCConnection::new("AppleSMC").unwrap();
 
     let mut timer = time::interval(Duration::from_millis(
         app.pinia()
@@ -77,26 +86,20 @@ pub fn start_sender<R: Runtime>(
         .unwrap_or(true);
 
     async_runtime::spawn(async move {
+        let mut estimator = Estimator::default();
+        let mut last = None;
         loop {
             select! {
                 _ = timer.tick() => {
-                    let smc = smc_conn.read_sensor();
-                    PowerUpdatedEvent::new_with(&smc, &status_bar_item, show_charging)
-                        .emit(&app)
-                        .unwrap();
-                    PowerTickEvent {
-                        data: (&get_mac_ioreg().unwrap(), &smc).into(),
-                    }.emit(&app).unwrap();
+                    last = sample_and_emit(
+                        &app, &mut source, &mut estimator, &status_bar_item, show_charging,
+                    );
                 }
                 Some(msg) = rx.recv() => match msg {
                     SenderMessage::ImmediateSend => {
-                        let smc = smc_conn.read_sensor();
-                        PowerUpdatedEvent::new_with(&smc, &status_bar_item, show_charging)
-                            .emit(&app)
-                            .unwrap();
-                        PowerTickEvent {
-                            data:  (&get_mac_ioreg().unwrap(), &smc).into()
-                        }.emit(&app).unwrap();
+                        last = sample_and_emit(
+                            &app, &mut source, &mut estimator, &status_bar_item, show_charging,
+                        );
                     },
                     SenderMessage::ChangeInterval(interval) => {
                         timer = time::interval(if interval < Duration::from_millis(500) {
@@ -108,15 +111,19 @@ pub fn start_sender<R: Runtime>(
                     },
                     SenderMessage::ChangeStatusBarItem(item) => {
                         status_bar_item = item;
-                        PowerUpdatedEvent::new_with(&smc_conn.read_sensor(), &status_bar_item, show_charging)
-                            .emit(&app)
-                            .unwrap();
+                        if let Some(data) = &last {
+                            PowerUpdatedEvent::new_with(data, &status_bar_item, show_charging)
+                                .emit(&app)
+                                .unwrap();
+                        }
                     },
                     SenderMessage::StatusBarShowCharging(show) => {
                         show_charging = show;
-                        PowerUpdatedEvent::new_with(&smc_conn.read_sensor(), &status_bar_item, show_charging)
-                            .emit(&app)
-                            .unwrap();
+                        if let Some(data) = &last {
+                            PowerUpdatedEvent::new_with(data, &status_bar_item, show_charging)
+                                .emit(&app)
+                                .unwrap();
+                        }
                     }
                 }
             }
@@ -124,10 +131,62 @@ pub fn start_sender<R: Runtime>(
     })
 }
 
+fn sample_and_emit<R: Runtime>(
+    app: &AppHandle<R>,
+    source: &mut impl PowerSource,
+    estimator: &mut Estimator,
+    status_bar_item: &StatusBarItem,
+    show_charging: bool,
+) -> Option<NormalizedResource> {
+    let data = source
+        .sample_normalized()
+        .inspect_err(|err| log::error!("Failed to sample local power data: {err}"))
+        .ok()?;
+
+    app.state::<BatteryDetailsState>()
+        .update("local", &data.battery);
+    PowerUpdatedEvent::new_with(&data, status_bar_item, show_charging)
+        .emit(app)
+        .unwrap();
+    PowerTickEvent {
+        estimate: estimator.update(&data, SystemTime::now()),
+        data: data.clone(),
+    }
+    .emit(app)
+    .unwrap();
+
+    Some(data)
+}
+
+/// The user's SMC sensor profiles if there are any, falling back to the
+/// bundled ones for the machines they don't cover.
+fn load_sensor_profiles<R: Runtime>(app: &AppHandle<R>) -> SensorProfiles {
+    let Ok(dir) = app.path().app_data_dir() else {
+        return SensorProfiles::bundled();
+    };
+    let user = USER_SMC_PROFILES
+        .iter()
+        .map(|name| dir.join(name))
+        .filter(|path| path.exists())
+        .find_map(|path| {
+            SensorProfiles::load(&path)
+                .inspect(|_| log::info!("Using SMC sensor profiles from {}", path.display()))
+                .inspect_err(|err| {
+                    log::error!("Ignoring SMC sensor profiles at {}: {err}", path.display())
+                })
+                .ok()
+        });
+    match user {
+        Some(user) => user.with_fallback(SensorProfiles::bundled()),
+        None => SensorProfiles::bundled(),
+    }
+}
+
 pub fn setup_sender_with_events<R: Runtime>(app: &impl Manager<R>) {
     let app = app.app_handle();
     let (sender_tx, rx) = mpsc::channel(10);
-    start_sender(app, rx);
+    let source = LocalSource::with_profiles(&load_sensor_profiles(app));
+    start_sender(app, source, rx);
 
     // send an immediate update when the main window is loaded
     let tx = sender_tx.clone();
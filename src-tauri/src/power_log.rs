@@ -0,0 +1,376 @@
+use std::{
+    mem,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use sqlx::SqlitePool;
+use tauri::{async_runtime, AppHandle, Manager};
+use tauri_specta::{Event, TypedEvent};
+use tokio::{select, sync::mpsc, time};
+use tpower::provider::NormalizedResource;
+
+use crate::{
+    database::{
+        expire_power_log, get_power_rollups, get_power_samples, roll_up_power_log,
+        save_power_samples, PowerLogBucket,
+    },
+    device::DevicePowerTickEvent,
+    local::PowerTickEvent,
+};
+
+/// How often buffered samples are written.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+/// How often the rollups are refreshed and old rows dropped.
+const ROLLUP_INTERVAL: Duration = Duration::from_secs(60);
+
+const MINUTE: i64 = 60;
+const HOUR: i64 = 60 * MINUTE;
+const DAY: i64 = 24 * HOUR;
+
+/// How long each resolution is kept, s.
+const RAW_RETENTION: i64 = 6 * HOUR;
+const MINUTE_RETENTION: i64 = 14 * DAY;
+const HOUR_RETENTION: i64 = 365 * DAY;
+
+/// Longest span answered with raw samples, then with minutes, when no
+/// resolution is asked for.
+const MAX_RAW_SPAN: i64 = 2 * HOUR;
+const MAX_MINUTE_SPAN: i64 = 3 * DAY;
+
+/// One row of `power_samples`.
+pub struct LoggedSample {
+    pub udid: String,
+    /// Seconds since the unix epoch
+    pub timestamp: i64,
+    pub system_load: f32,
+    pub system_in: f32,
+    pub battery_power: f32,
+    pub battery_level: i32,
+    pub is_charging: bool,
+}
+
+impl LoggedSample {
+    fn new(udid: String, timestamp: i64, data: &NormalizedResource) -> Self {
+        Self {
+            udid,
+            timestamp,
+            system_load: data.system_load,
+            system_in: data.system_in,
+            battery_power: data.battery_power,
+            battery_level: data.battery_level,
+            is_charging: data.is_charging,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum PowerLogResolution {
+    Raw,
+    Minute,
+    Hour,
+}
+
+impl PowerLogResolution {
+    /// The finest resolution still kept for `from` that doesn't return too
+    /// many rows for `from..to`.
+    fn pick(from: i64, to: i64, now: i64) -> Self {
+        let span = to - from;
+        if span <= MAX_RAW_SPAN && from >= now - RAW_RETENTION {
+            Self::Raw
+        } else if span <= MAX_MINUTE_SPAN && from >= now - MINUTE_RETENTION {
+            Self::Minute
+        } else {
+            Self::Hour
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct PowerLog {
+    pub resolution: PowerLogResolution,
+    /// Oldest first, raw samples come as buckets of one
+    pub buckets: Vec<PowerLogBucket>,
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+/// The log of `udid` within `from..to`, in seconds since the unix epoch.
+pub async fn query_power_log(
+    db: &SqlitePool,
+    udid: &str,
+    from: i64,
+    to: i64,
+    resolution: Option<PowerLogResolution>,
+) -> Result<PowerLog, sqlx::Error> {
+    let resolution = resolution.unwrap_or_else(|| PowerLogResolution::pick(from, to, now()));
+    let buckets = match resolution {
+        PowerLogResolution::Raw => get_power_samples(db, udid, from, to).await?,
+        PowerLogResolution::Minute => get_power_rollups(db, udid, MINUTE, from, to).await?,
+        PowerLogResolution::Hour => get_power_rollups(db, udid, HOUR, from, to).await?,
+    };
+    Ok(PowerLog {
+        resolution,
+        buckets,
+    })
+}
+
+async fn flush(db: &SqlitePool, buffer: &mut Vec<LoggedSample>) {
+    if buffer.is_empty() {
+        return;
+    }
+    let samples = mem::take(buffer);
+    if let Err(e) = save_power_samples(db, &samples).await {
+        log::error!("power log save failed: {:#?}", e);
+    }
+}
+
+/// The minute and hour buckets to recompute at `now` when samples since
+/// `since` may have changed. Only complete buckets, the current one is
+/// rolled up next time.
+fn rollup_ranges(since: i64, now: i64) -> ((i64, i64), (i64, i64)) {
+    (
+        (since / MINUTE * MINUTE, now / MINUTE * MINUTE),
+        (since / HOUR * HOUR, now / HOUR * HOUR),
+    )
+}
+
+/// Raw samples before the first cutoff are dropped, and for each resolution
+/// the buckets before its cutoff. Aligned down to whole buckets, so every
+/// bucket still rolled up has all of its samples.
+fn expiry_cutoffs(now: i64) -> (i64, [(i64, i64); 2]) {
+    ((now - RAW_RETENTION) / MINUTE * MINUTE, [
+        (MINUTE, (now - MINUTE_RETENTION) / HOUR * HOUR),
+        (HOUR, (now - HOUR_RETENTION) / HOUR * HOUR),
+    ])
+}
+
+/// Refresh the buckets that may have changed since `since`, and drop
+/// whatever is past its retention.
+async fn roll_up(db: &SqlitePool, since: i64, now: i64) {
+    let (minutes, hours) = rollup_ranges(since, now);
+    if let Err(e) = roll_up_power_log(db, minutes, hours).await {
+        log::error!("power log rollup failed: {:#?}", e);
+        return;
+    }
+
+    let (samples_before, expired) = expiry_cutoffs(now);
+    if let Err(e) = expire_power_log(db, samples_before, &expired).await {
+        log::error!("power log expiry failed: {:#?}", e);
+    }
+}
+
+fn spawn_power_logger(app: AppHandle, mut rx: mpsc::Receiver<LoggedSample>) {
+    async_runtime::spawn(async move {
+        let db = app.state::<SqlitePool>();
+        let mut buffer = vec![];
+        let mut flush_timer = time::interval(FLUSH_INTERVAL);
+        let mut rollup_timer = time::interval(ROLLUP_INTERVAL);
+        // catch up on everything still kept raw, e.g. after a restart. The
+        // minute at the cutoff is still complete, earlier ones may not be
+        let mut rolled = expiry_cutoffs(now()).0;
+
+        loop {
+            select! {
+                sample = rx.recv() => match sample {
+                    Some(sample) => buffer.push(sample),
+                    None => break,
+                },
+                _ = flush_timer.tick() => flush(&db, &mut buffer).await,
+                _ = rollup_timer.tick() => {
+                    flush(&db, &mut buffer).await;
+                    roll_up(&db, rolled, now()).await;
+                    // the last hour may have been rolled up before it was
+                    // complete, everything before it is settled
+                    rolled = now() / HOUR * HOUR - HOUR;
+                }
+            }
+        }
+        flush(&db, &mut buffer).await;
+    });
+}
+
+pub fn setup_power_logger(app: AppHandle) {
+    let (tx, rx) = mpsc::channel(10);
+    let tx_cloned = tx.clone();
+    PowerTickEvent::listen(&app, move |TypedEvent { payload, .. }| {
+        let tx = tx_cloned.clone();
+        async_runtime::spawn(async move {
+            tx.send(LoggedSample::new("local".to_string(), now(), &payload.data))
+                .await
+                .unwrap_or_else(|err| {
+                    log::error!("Failed to send PowerTickEvent: {:#?}", err);
+                })
+        });
+    });
+
+    let tx_cloned = tx.clone();
+    DevicePowerTickEvent::listen(&app, move |TypedEvent { payload, .. }| {
+        let tx = tx_cloned.clone();
+        async_runtime::spawn(async move {
+            tx.send(LoggedSample::new(payload.udid, now(), &payload.data))
+                .await
+                .unwrap_or_else(|err| {
+                    log::error!("Failed to send DevicePowerTickEvent: {:#?}", err);
+                })
+        });
+    });
+    spawn_power_logger(app.clone(), rx);
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::{migrate, sqlite::SqlitePoolOptions};
+
+    use super::*;
+
+    const START: i64 = 1_700_000_000 / HOUR * HOUR;
+
+    async fn db() -> SqlitePool {
+        // every connection to `:memory:` opens a database of its own
+        let db = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        migrate!().run(&db).await.unwrap();
+        db
+    }
+
+    fn sample(timestamp: i64, system_load: f32) -> LoggedSample {
+        LoggedSample {
+            udid: "local".to_string(),
+            timestamp,
+            system_load,
+            system_in: 0.,
+            battery_power: 0.,
+            battery_level: 50,
+            is_charging: false,
+        }
+    }
+
+    /// `(bucket, samples, systemLoadAvg)` of each bucket.
+    async fn rollups(db: &SqlitePool, resolution: i64) -> Vec<(i64, i64, f64)> {
+        get_power_rollups(db, "local", resolution, 0, i64::MAX)
+            .await
+            .unwrap()
+            .iter()
+            .map(|bucket| {
+                let bucket = serde_json::to_value(bucket).unwrap();
+                (
+                    bucket["bucket"].as_i64().unwrap(),
+                    bucket["samples"].as_i64().unwrap(),
+                    bucket["systemLoadAvg"].as_f64().unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rolls_up_complete_buckets_only() {
+        let now = START + 2 * HOUR + 5 * MINUTE + 30;
+        let (minutes, hours) = rollup_ranges(START + HOUR + 30, now);
+
+        assert_eq!(minutes, (START + HOUR, START + 2 * HOUR + 5 * MINUTE));
+        assert_eq!(hours, (START + HOUR, START + 2 * HOUR));
+    }
+
+    #[test]
+    fn expires_whole_buckets() {
+        let now = START + DAY + 90;
+        let (samples_before, expired) = expiry_cutoffs(now);
+
+        assert_eq!(samples_before, START + DAY - RAW_RETENTION + MINUTE);
+        assert_eq!(samples_before % MINUTE, 0);
+        for (resolution, before) in expired {
+            assert_eq!(before % HOUR, 0, "{resolution}");
+        }
+        assert_eq!(expired[0].1, START + DAY - MINUTE_RETENTION);
+    }
+
+    #[test]
+    fn picks_the_finest_resolution_kept() {
+        let now = START + DAY;
+
+        assert_eq!(
+            PowerLogResolution::pick(now - HOUR, now, now),
+            PowerLogResolution::Raw
+        );
+        assert_eq!(
+            PowerLogResolution::pick(now - RAW_RETENTION - HOUR, now - RAW_RETENTION, now),
+            PowerLogResolution::Minute
+        );
+        assert_eq!(
+            PowerLogResolution::pick(now - DAY, now, now),
+            PowerLogResolution::Minute
+        );
+        assert_eq!(
+            PowerLogResolution::pick(now - 7 * DAY, now, now),
+            PowerLogResolution::Hour
+        );
+    }
+
+    #[tokio::test]
+    async fn averages_samples_into_minutes_and_hours() {
+        let db = db().await;
+        // 10 W for the first minute, 20 W for the second
+        let samples = (0..12)
+            .map(|i| sample(START + i * 10, if i < 6 { 10. } else { 20. }))
+            .collect::<Vec<_>>();
+        save_power_samples(&db, &samples).await.unwrap();
+
+        roll_up(&db, START, START + HOUR + 1).await;
+
+        assert_eq!(rollups(&db, MINUTE).await, [
+            (START, 6, 10.),
+            (START + MINUTE, 6, 20.)
+        ]);
+        assert_eq!(rollups(&db, HOUR).await, [(START, 12, 15.)]);
+    }
+
+    #[tokio::test]
+    async fn keeps_complete_minutes_across_expiry_and_restarts() {
+        let db = db().await;
+        let samples = (0..6)
+            .map(|i| sample(START + i * 10, 10. + i as f32))
+            .collect::<Vec<_>>();
+        save_power_samples(&db, &samples).await.unwrap();
+        roll_up(&db, START, START + 2 * MINUTE).await;
+        assert_eq!(rollups(&db, MINUTE).await, [(START, 6, 12.5)]);
+
+        // half a minute past the raw retention of the first minute
+        let now = START + RAW_RETENTION + 30;
+        roll_up(&db, expiry_cutoffs(now).0, now).await;
+        assert_eq!(rollups(&db, MINUTE).await, [(START, 6, 12.5)]);
+        assert_eq!(
+            get_power_samples(&db, "local", 0, i64::MAX)
+                .await
+                .unwrap()
+                .len(),
+            6
+        );
+
+        // restarted within the same minute
+        let now = START + RAW_RETENTION + 50;
+        roll_up(&db, expiry_cutoffs(now).0, now).await;
+        assert_eq!(rollups(&db, MINUTE).await, [(START, 6, 12.5)]);
+
+        // restarted once the minute is gone from the raw samples
+        let now = START + MINUTE + RAW_RETENTION + 10;
+        roll_up(&db, expiry_cutoffs(now).0, now).await;
+        assert_eq!(rollups(&db, MINUTE).await, [(START, 6, 12.5)]);
+        assert!(get_power_samples(&db, "local", 0, i64::MAX)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use chrono::Local;
+use sqlx::SqlitePool;
+use tauri::{async_runtime, AppHandle, Manager};
+use tauri_specta::{Event, TypedEvent};
+use tokio::sync::mpsc;
+use tpower::{provider::NormalizedResource, util::get_mac_name};
+
+use crate::{
+    database::save_battery_snapshot,
+    device::{DevicePowerTickEvent, DeviceState},
+    local::PowerTickEvent,
+};
+
+/// How often the snapshot of the day is written while samples come in.
+const SAVE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// One device on one day, merged into its row by
+/// [`save_battery_snapshot`].
+pub struct DaySnapshot {
+    pub udid: String,
+    pub name: String,
+    pub is_remote: bool,
+    pub day: String,
+    pub max_capacity: i32,
+    pub design_capacity: i32,
+    pub cycle_count: i32,
+    pub min_temperature: f32,
+    pub max_temperature: f32,
+    pub adapter_name: Option<String>,
+    pub timestamp: i64,
+}
+
+impl DaySnapshot {
+    fn new(
+        udid: String,
+        name: String,
+        is_remote: bool,
+        day: String,
+        data: &NormalizedResource,
+    ) -> Self {
+        let mut snapshot = Self {
+            udid,
+            name,
+            is_remote,
+            day,
+            max_capacity: 0,
+            design_capacity: 0,
+            cycle_count: 0,
+            min_temperature: data.temperature,
+            max_temperature: data.temperature,
+            adapter_name: None,
+            timestamp: 0,
+        };
+        snapshot.update(data);
+        snapshot
+    }
+
+    fn update(&mut self, data: &NormalizedResource) {
+        self.max_capacity = data.max_capacity;
+        self.design_capacity = data.design_capacity;
+        self.cycle_count = data.cycle_count;
+        self.min_temperature = self.min_temperature.min(data.temperature);
+        self.max_temperature = self.max_temperature.max(data.temperature);
+        if data.adapter_name.is_some() {
+            self.adapter_name = data.adapter_name.clone();
+        }
+        self.timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+    }
+}
+
+struct SnapshotStage {
+    snapshot: DaySnapshot,
+    saved: Option<Instant>,
+}
+
+fn device_name(app: &AppHandle, udid: &str, is_remote: bool) -> String {
+    if is_remote {
+        app.state::<DeviceState>()
+            .read()
+            .unwrap()
+            .get(udid)
+            .map(|d| d.0.clone())
+    } else {
+        get_mac_name()
+    }
+    .unwrap_or_default()
+}
+
+async fn save(db: &SqlitePool, snapshot: &DaySnapshot) {
+    match save_battery_snapshot(db, snapshot).await {
+        Ok(_) => log::info!("snapshot of {} on {} saved", snapshot.udid, snapshot.day),
+        Err(e) => log::error!("snapshot save failed: {:#?}", e),
+    }
+}
+
+fn spawn_snapshot_recorder(
+    app: AppHandle,
+    mut rx: mpsc::Receiver<(String, bool, NormalizedResource)>,
+) {
+    async_runtime::spawn(async move {
+        let db = app.state::<SqlitePool>();
+        let mut staged: HashMap<String, SnapshotStage> = HashMap::new();
+
+        while let Some((udid, is_remote, data)) = rx.recv().await {
+            // e.g. a device that hasn't reported its battery yet
+            if data.max_capacity <= 0 {
+                continue;
+            }
+
+            let day = Local::now().format("%Y-%m-%d").to_string();
+            if staged
+                .get(&udid)
+                .is_some_and(|stage| stage.snapshot.day != day)
+            {
+                // the day is over, keep what was seen since the last save
+                let previous = staged.remove(&udid).unwrap();
+                save(&db, &previous.snapshot).await;
+            }
+
+            let stage = match staged.entry(udid.clone()) {
+                Entry::Occupied(entry) => {
+                    let stage = entry.into_mut();
+                    stage.snapshot.update(&data);
+                    stage
+                }
+                Entry::Vacant(entry) => entry.insert(SnapshotStage {
+                    snapshot: DaySnapshot::new(
+                        udid.clone(),
+                        device_name(&app, &udid, is_remote),
+                        is_remote,
+                        day,
+                        &data,
+                    ),
+                    saved: None,
+                }),
+            };
+
+            if stage
+                .saved
+                .is_none_or(|saved| saved.elapsed() >= SAVE_INTERVAL)
+            {
+                save(&db, &stage.snapshot).await;
+                stage.saved = Some(Instant::now());
+            }
+        }
+    });
+}
+
+pub fn setup_snapshot_recorder(app: AppHandle) {
+    let (tx, rx) = mpsc::channel(10);
+    let tx_cloned = tx.clone();
+    PowerTickEvent::listen(&app, move |TypedEvent { payload, .. }| {
+        let tx = tx_cloned.clone();
+        async_runtime::spawn(async move {
+            tx.send(("local".to_string(), false, payload.data))
+                .await
+                .unwrap_or_else(|err| {
+                    log::error!("Failed to send PowerTickEvent: {:#?}", err);
+                })
+        });
+    });
+
+    let tx_cloned = tx.clone();
+    DevicePowerTickEvent::listen(&app, move |TypedEvent { payload, .. }| {
+        let tx = tx_cloned.clone();
+        async_runtime::spawn(async move {
+            tx.send((payload.udid, true, payload.data))
+                .await
+                .unwrap_or_else(|err| {
+                    log::error!("Failed to send DevicePowerTickEvent: {:#?}", err);
+                })
+        });
+    });
+    spawn_snapshot_recorder(app.clone(), rx);
+}
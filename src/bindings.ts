@@ -20,6 +20,24 @@ async getDeviceName(id: string) : Promise<[string, InterfaceType[]] | null> {
 async getMacName() : Promise<string | null> {
     return await TAURI_INVOKE("get_mac_name");
 },
+/**
+ * What the battery of a device reports beyond the power readings, `local`
+ * for this Mac. `None` until the device has been sampled.
+ */
+async getBatteryDetails(udid: string) : Promise<BatteryDetails | null> {
+    return await TAURI_INVOKE("get_battery_details", { udid });
+},
+/**
+ * Everything `AppleSmartBattery` reports, for debugging and bug reports.
+ */
+async getRawIoreg() : Promise<Result<JsonValue, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_raw_ioreg") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
 async switchTheme(theme: Theme) : Promise<void> {
     await TAURI_INVOKE("switch_theme", { theme });
 },
@@ -46,6 +64,57 @@ async deleteHistoryById(id: number) : Promise<Result<number, string>> {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
 }
+},
+async getAllDischargeHistory() : Promise<Result<DischargeHistory[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_all_discharge_history") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async deleteDischargeHistoryById(id: number) : Promise<Result<number, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("delete_discharge_history_by_id", { id }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * State of health of a device, fitted over its charging history and daily
+ * snapshots.
+ */
+async getBatteryHealth(udid: string) : Promise<Result<HealthReport | null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_battery_health", { udid }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * One row per day, oldest first.
+ */
+async getBatterySnapshots(udid: string) : Promise<Result<BatterySnapshot[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_battery_snapshots", { udid }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Power log of a device within `from..to`, seconds since the unix epoch.
+ * Without a resolution the finest one still kept for the range is used.
+ */
+async getPowerLog(udid: string, from: number, to: number, resolution: PowerLogResolution | null) : Promise<Result<PowerLog, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_power_log", { udid, from, to, resolution }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
 }
 }
 
@@ -97,13 +166,142 @@ export type Action =
  * Unsubcribing and resubscribing may recover the notification system.
  */
 "NotificationStopped" | "Paired"
+export type BatteryData = { 
+/**
+ * mV, one per cell
+ */
+cellVoltage: number[] | null; 
+/**
+ * mAh, one per cell
+ */
+qmax: number[] | null; stateOfCharge: number | null; designCycleCount: number | null; lifetimeData: LifetimeData | null }
+/**
+ * What the gas gauge and charger report beyond the power readings, each
+ * `None` if the battery doesn't report it.
+ */
+export type BatteryDetails = { serial: string | null; manufactureDate: number | null; installed: boolean | null; permanentFailureStatus: number | null; batteryData: BatteryData | null; chargerData: ChargerData | null }
+export type BatterySnapshot = { id: number; udid: string; name: string; isRemote: number; 
+/**
+ * `YYYY-MM-DD` in local time
+ */
+day: string; maxCapacity: number; designCapacity: number; cycleCount: number; minTemperature: number; maxTemperature: number; adapterName: string | null; timestamp: number }
+/**
+ * The capacity of a battery at one point in time.
+ */
+export type CapacitySample = { 
+/**
+ * Seconds since the unix epoch
+ */
+timestamp: number; cycleCount: number; 
+/**
+ * mAh
+ */
+maxCapacity: number; 
+/**
+ * mAh
+ */
+designCapacity: number }
+export type ChargerData = { chargerId: number | null; 
+/**
+ * mA
+ */
+chargingCurrent: number | null; 
+/**
+ * mV
+ */
+chargingVoltage: number | null; notChargingReason: number | null; chargerInhibitReason: number | null; vacVoltageLimit: number | null }
 export type ChargingHistory = { id: number; fromLevel: number; endLevel: number; chargingTime: number; timestamp: number; name: string; udid: string; isRemote: number; adapterName: string }
-export type ChargingHistoryDetail = { avg: NormalizedData; peak: NormalizedData; curve: NormalizedResource[]; raw: string[] }
+export type ChargingHistoryDetail = { avg: NormalizedData; peak: NormalizedData; curve: NormalizedResource[]; raw: string[]; phases?: PhaseSpan[] }
+export type ChargingPhase = "constantCurrent" | 
+/**
+ * The current tapers off
+ */
+"constantVoltage" | "trickle" | 
+/**
+ * Charging paused, e.g. by optimized charging
+ */
+"hold"
 export type DeviceEvent = { udid: string; name: string; interface: InterfaceType; action: Action }
-export type DevicePowerTickEvent = { udid: string; data: NormalizedResource }
+export type DevicePowerTickEvent = { udid: string; data: NormalizedResource; estimate: Estimate | null }
+export type DischargeHistory = { id: number; fromLevel: number; endLevel: number; dischargingTime: number; timestamp: number; name: string; udid: string; isRemote: number; 
+/**
+ * W
+ */
+avgSystemLoad: number; peakSystemLoad: number; 
+/**
+ * Wh drawn from the battery
+ */
+energy: number; 
+/**
+ * Seconds a full battery lasts at the rate of this discharge, unknown
+ * when the level didn't drop
+ */
+estimatedRuntime: number | null }
 export type Duration = { secs: number; nanos: number }
+export type Estimate = { kind: EstimateKind; remaining: Duration; 
+/**
+ * Smoothed charge or discharge current, mA
+ */
+rate: number; 
+/**
+ * 0 to 1, low while the rate settles or swings
+ */
+confidence: number }
+export type EstimateKind = "toFull" | "toEmpty"
+/**
+ * Fan speeds in RPM.
+ */
+export type FanData = { actual: number; min: number; max: number; target: number }
+/**
+ * When the state of health reaches `threshold` at the current fade.
+ */
+export type HealthProjection = { threshold: number; 
+/**
+ * Already at or below the threshold
+ */
+reached: boolean; cycleCount: number | null; 
+/**
+ * Seconds since the unix epoch
+ */
+timestamp: number | null }
+export type HealthReport = { 
+/**
+ * The latest sample
+ */
+current: CapacitySample; stateOfHealth: number; 
+/**
+ * Percentage points lost per cycle, `None` until the history spans more
+ * than one cycle count
+ */
+fadePerCycle: number | null; 
+/**
+ * Percentage points lost per day, `None` until the history spans more
+ * than a day
+ */
+fadePerDay: number | null; projections: HealthProjection[]; 
+/**
+ * Samples the fades were fitted over
+ */
+samples: number }
 export type HistoryRecordedEvent = null
 export type InterfaceType = "Unknown" | "USB" | "WiFi"
+export type JsonValue = null | boolean | number | string | JsonValue[] | { [key in string]: JsonValue }
+/**
+ * Extremes the gas gauge recorded over the life of the battery.
+ */
+export type LifetimeData = { maximumTemperature: number | null; minimumTemperature: number | null; averageTemperature: number | null; 
+/**
+ * mV
+ */
+maximumPackVoltage: number | null; minimumPackVoltage: number | null; 
+/**
+ * mA
+ */
+maximumChargeCurrent: number | null; maximumDischargeCurrent: number | null; 
+/**
+ * hours
+ */
+totalOperatingTime: number | null }
 export type NormalizedData = { systemIn: number; systemLoad: number; batteryPower: number; adapterPower: number; efficiencyLoss: number; 
 /**
  * 0 if not available
@@ -121,12 +319,53 @@ brightnessPower: number;
 /**
  * 0 if not available
  */
-heatpipePower: number; batteryLevel: number; absoluteBatteryLevel: number; temperature: number; adapterWatts: number; adapterVoltage: number; adapterAmperage: number }) & { isLocal: boolean; isCharging: boolean; timeRemain: Duration; lastUpdate: number; adapterName: string | null; cycleCount: number; currentCapacity: number; maxCapacity: number; designCapacity?: number }
-export type PowerTickEvent = { data: NormalizedResource }
+heatpipePower: number; batteryLevel: number; absoluteBatteryLevel: number; temperature: number; adapterWatts: number; adapterVoltage: number; adapterAmperage: number }) & { isLocal: boolean; isCharging: boolean; timeRemain: Duration; lastUpdate: number; adapterName: string | null; cycleCount: number; currentCapacity: number; maxCapacity: number; designCapacity?: number; 
+/**
+ * V
+ */
+voltage?: number; 
+/**
+ * Whether an adapter is attached, `None` if the battery doesn't say
+ */
+externalConnected?: boolean | null; 
+/**
+ * Only available for the local machine
+ */
+thermal?: ThermalData | null }
+export type PhaseSpan = { phase: ChargingPhase; 
+/**
+ * Seconds since the unix epoch
+ */
+start: number; end: number; fromLevel: number; endLevel: number; 
+/**
+ * W
+ */
+avgPower: number }
+export type PowerLog = { resolution: PowerLogResolution; 
+/**
+ * Oldest first, raw samples come as buckets of one
+ */
+buckets: PowerLogBucket[] }
+export type PowerLogBucket = { 
+/**
+ * Start of the bucket, seconds since the unix epoch
+ */
+bucket: number; samples: number; systemLoadMin: number; systemLoadAvg: number; systemLoadMax: number; systemInMin: number; systemInAvg: number; systemInMax: number; batteryPowerMin: number; batteryPowerAvg: number; batteryPowerMax: number; batteryLevelMin: number; batteryLevelAvg: number; batteryLevelMax: number }
+export type PowerLogResolution = "raw" | "minute" | "hour"
+export type PowerTickEvent = { data: NormalizedResource; estimate: Estimate | null }
 export type PowerUpdatedEvent = string
-export type PreferenceEvent = { theme: Theme } | { animationsEnabled: boolean } | { updateInterval: number } | { language: string } | { statusBarItem: StatusBarItem } | { statusBarShowCharging: boolean }
+export type PreferenceEvent = { theme: Theme } | { animationsEnabled: boolean } | { updateInterval: number } | { language: string } | { statusBarItem: StatusBarItem } | { statusBarShowCharging: boolean } | { metricsExporter: boolean } | { metricsPort: number } | { metricsAllowRemote: boolean }
 export type StatusBarItem = "system" | "screen" | "heatpipe"
 export type Theme = "light" | "dark" | "system"
+export type ThermalData = { 
+/**
+ * °C, `None` if the profile has no readable key for it
+ */
+cpu: number | null; gpu: number | null; soc: number | null; ambient: number | null; 
+/**
+ * Empty on fanless machines
+ */
+fans: FanData[] }
 export type WindowLoadedEvent = null
 
 /** tauri-specta globals **/
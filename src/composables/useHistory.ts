@@ -1,4 +1,4 @@
-import { type ChargingHistory, commands, type Result } from '@/bindings'
+import { type ChargingHistory, commands, type DischargeHistory, type Result } from '@/bindings'
 
 export function useAsyncData<T>(promiseFn: () => Promise<Result<T, string>>) {
   const data = ref<T | null>(null)
@@ -36,10 +36,12 @@ export function useAsyncData<T>(promiseFn: () => Promise<Result<T, string>>) {
 
 const selectedItem = ref(null as ChargingHistory | null)
 const history = useAsyncData<ChargingHistory[]>(() => commands.getAllChargingHistory())
+const discharges = useAsyncData<DischargeHistory[]>(() => commands.getAllDischargeHistory())
 
 export function useHistory() {
   return {
     selectedItem,
     history,
+    discharges,
   }
 }
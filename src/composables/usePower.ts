@@ -1,4 +1,4 @@
-import type { InterfaceType, NormalizedResource } from '@/bindings'
+import type { Estimate, InterfaceType, NormalizedResource } from '@/bindings'
 import type { Reactive } from 'vue'
 import { events } from '@/bindings'
 import { useDocumentVisibility } from '@vueuse/core'
@@ -8,6 +8,8 @@ import { useTab } from './useTab'
 
 const MAX_STATISTICS_LENGTH = 20
 const LOCAL_UPDATE_INTERVAL = 3
+// below this the reported remaining time is shown instead of the estimate
+const MIN_ESTIMATE_CONFIDENCE = 0.5
 
 export interface StatisticData {
   'time': string
@@ -20,6 +22,7 @@ export interface StatisticData {
 
 interface RawPowerData {
   data: NormalizedResource
+  estimate: Estimate | null
   statistics: StatisticData[]
 }
 
@@ -30,13 +33,15 @@ function trimStatistics(statistics: StatisticData[]) {
 
 const localPowerData: Reactive<RawPowerData> = reactive({
   data: {} as NormalizedResource,
+  estimate: null,
   statistics: [],
 })
 
 let localUpdateCount = 0
 
-events.powerTickEvent.listen(async ({ payload: { data } }) => {
+events.powerTickEvent.listen(async ({ payload: { data, estimate } }) => {
   localPowerData.data = data
+  localPowerData.estimate = estimate
 
   localUpdateCount++
   if (localUpdateCount < LOCAL_UPDATE_INTERVAL)
@@ -55,9 +60,10 @@ events.powerTickEvent.listen(async ({ payload: { data } }) => {
   })
 })
 
-events.devicePowerTickEvent.listen(({ payload: { data, udid } }) => {
+events.devicePowerTickEvent.listen(({ payload: { data, estimate, udid } }) => {
   const deviceData = getOrCreateDeviceData(udid)
   deviceData.data = data
+  deviceData.estimate = estimate
 
   const statistics = deviceData.statistics
   trimStatistics(statistics)
@@ -94,6 +100,7 @@ function getOrCreateDeviceData(udid: string): RemotePowerData {
   if (!power.remote[udid]) {
     power.remote[udid] = {
       data: {} as NormalizedResource,
+      estimate: null,
       statistics: [],
       name: '',
       offline: false,
@@ -125,9 +132,16 @@ const currentPower = computed<RawPowerData>(() => {
   return tab.value === 'local' ? power.local : power.remote[tab.value] || {}
 })
 
+function remainSecs({ data, estimate }: RawPowerData) {
+  if (estimate && estimate.confidence >= MIN_ESTIMATE_CONFIDENCE)
+    return estimate.remaining.secs
+  return data?.timeRemain?.secs ?? 0
+}
+
 export function usePower() {
   return computed(() => ({
     ...currentPower.value.data,
+    remainSecs: remainSecs(currentPower.value),
     isLoading: Object.keys(currentPower.value.data).length === 0 || vis.value === 'hidden',
     isRemote: tab.value !== 'local',
     statistics: currentPower.value.statistics,
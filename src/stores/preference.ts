@@ -9,6 +9,9 @@ export const usePreference = defineStore('preference', () => {
   const language = ref('en')
   const statusBarItem = ref<StatusBarItem>('system')
   const statusBarShowCharging = ref(true)
+  const metricsExporter = ref(false)
+  const metricsPort = ref(9101)
+  const metricsAllowRemote = ref(false)
 
   return {
     theme,
@@ -17,6 +20,9 @@ export const usePreference = defineStore('preference', () => {
     language,
     statusBarItem,
     statusBarShowCharging,
+    metricsExporter,
+    metricsPort,
+    metricsAllowRemote,
   }
 }, {
   tauri: {